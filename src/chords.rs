@@ -0,0 +1,221 @@
+use std::collections::BTreeSet;
+
+use music_analyzer_generator::{analyzer::MelodyDirection, scales::RootedScale};
+
+use crate::{keys::Key, note_events::NoteEvent, reduction::CHORD_TOLERANCE};
+
+const MIN_CHORD_PITCH_CLASSES: usize = 3;
+pub(crate) const NUM_PITCH_CLASSES: u8 = 12;
+/// Octave used when spelling pitch classes.
+pub(crate) const SPELLING_OCTAVE: u8 = 60;
+/// Semitones above the tonic of each degree of the major scale.
+pub(crate) const MAJOR_STEPS: [u8; 7] = [0, 2, 4, 5, 7, 9, 11];
+const NUMERALS: [&str; 7] = ["I", "II", "III", "IV", "V", "VI", "VII"];
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ChordQuality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Suspended2,
+    Suspended4,
+    Dominant7,
+    Major7,
+    Minor7,
+    HalfDiminished7,
+    Diminished7,
+}
+
+impl ChordQuality {
+    pub fn all() -> [Self; 11] {
+        [
+            Self::Major,
+            Self::Minor,
+            Self::Diminished,
+            Self::Augmented,
+            Self::Suspended2,
+            Self::Suspended4,
+            Self::Dominant7,
+            Self::Major7,
+            Self::Minor7,
+            Self::HalfDiminished7,
+            Self::Diminished7,
+        ]
+    }
+
+    /// Semitones above the root of each chord tone, root included.
+    pub fn intervals(&self) -> &'static [u8] {
+        match self {
+            Self::Major => &[0, 4, 7],
+            Self::Minor => &[0, 3, 7],
+            Self::Diminished => &[0, 3, 6],
+            Self::Augmented => &[0, 4, 8],
+            Self::Suspended2 => &[0, 2, 7],
+            Self::Suspended4 => &[0, 5, 7],
+            Self::Dominant7 => &[0, 4, 7, 10],
+            Self::Major7 => &[0, 4, 7, 11],
+            Self::Minor7 => &[0, 3, 7, 10],
+            Self::HalfDiminished7 => &[0, 3, 6, 10],
+            Self::Diminished7 => &[0, 3, 6, 9],
+        }
+    }
+
+    pub fn symbol_suffix(&self) -> &'static str {
+        match self {
+            Self::Major => "",
+            Self::Minor => "m",
+            Self::Diminished => "dim",
+            Self::Augmented => "aug",
+            Self::Suspended2 => "sus2",
+            Self::Suspended4 => "sus4",
+            Self::Dominant7 => "7",
+            Self::Major7 => "maj7",
+            Self::Minor7 => "m7",
+            Self::HalfDiminished7 => "m7b5",
+            Self::Diminished7 => "dim7",
+        }
+    }
+
+    fn is_minor(&self) -> bool {
+        matches!(
+            self,
+            Self::Minor
+                | Self::Diminished
+                | Self::Minor7
+                | Self::HalfDiminished7
+                | Self::Diminished7
+        )
+    }
+
+    fn numeral_suffix(&self) -> &'static str {
+        match self {
+            Self::Major | Self::Minor => "",
+            Self::Diminished => "\u{b0}",
+            Self::Augmented => "+",
+            Self::Suspended2 => "sus2",
+            Self::Suspended4 => "sus4",
+            Self::Dominant7 | Self::Minor7 => "7",
+            Self::Major7 => "maj7",
+            Self::HalfDiminished7 => "\u{f8}7",
+            Self::Diminished7 => "\u{b0}7",
+        }
+    }
+}
+
+/// A chord sounding from `onset` for `duration` seconds. Pitches are pitch classes.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Chord {
+    pub onset: f64,
+    pub duration: f64,
+    pub root: u8,
+    pub bass: u8,
+    pub quality: ChordQuality,
+}
+
+impl Chord {
+    /// Names the chord whose pitch classes are exactly `pitch_classes`,
+    /// preferring `bass` as the root when the notes allow more than one.
+    pub fn recognize(pitch_classes: &BTreeSet<u8>, bass: u8) -> Option<(u8, ChordQuality)> {
+        let roots = std::iter::once(bass).chain(pitch_classes.iter().copied());
+        for root in roots {
+            for quality in ChordQuality::all() {
+                let tones = quality
+                    .intervals()
+                    .iter()
+                    .map(|i| (root + i) % NUM_PITCH_CLASSES)
+                    .collect::<BTreeSet<_>>();
+                if tones == *pitch_classes {
+                    return Some((root, quality));
+                }
+            }
+        }
+        None
+    }
+
+    /// The chord symbol, such as "F#m7" or "C/E", spelled within `scale`.
+    pub fn symbol(&self, scale: &RootedScale) -> String {
+        let root = spell(self.root, scale);
+        let suffix = self.quality.symbol_suffix();
+        if self.bass == self.root {
+            format!("{root}{suffix}")
+        } else {
+            format!("{root}{suffix}/{}", spell(self.bass, scale))
+        }
+    }
+
+    /// The Roman numeral of the chord relative to the tonic of `key`, counting
+    /// degrees in the key's own mode. Roots between degrees are written as
+    /// the degree above lowered, or the degree below raised when none is above.
+    pub fn numeral(&self, key: &Key) -> String {
+        let steps = key.mode.steps();
+        let step = (self.root + NUM_PITCH_CLASSES - key.tonic) % NUM_PITCH_CLASSES;
+        let degree_of = |step: u8| steps.iter().position(|s| *s == step);
+        let (accidental, degree) = match degree_of(step) {
+            Some(degree) => ("", degree),
+            None => match degree_of(step + 1) {
+                Some(degree) => ("b", degree),
+                None => ("#", degree_of(step - 1).unwrap()),
+            },
+        };
+        let numeral = if self.quality.is_minor() {
+            NUMERALS[degree].to_lowercase()
+        } else {
+            NUMERALS[degree].to_string()
+        };
+        format!("{accidental}{numeral}{}", self.quality.numeral_suffix())
+    }
+}
+
+/// Names `pitch_class` as it is spelled within `scale`.
+pub(crate) fn spell(pitch_class: u8, scale: &RootedScale) -> String {
+    let pitch = SPELLING_OCTAVE + pitch_class;
+    let (name, _, accidental) = scale.matching_pitch(pitch, MelodyDirection::Ascending);
+    match accidental {
+        Some(accidental) => format!("{}", name.with_acc(accidental)),
+        None => format!("{name}"),
+    }
+}
+
+/// Recognizes the chords in `events`: each time notes are struck, the notes
+/// then sounding are named if they form a known chord, and repeats of the same
+/// chord are merged.
+pub fn chords(events: &[NoteEvent]) -> Vec<Chord> {
+    let mut onsets = events.iter().map(|e| e.onset).collect::<Vec<_>>();
+    onsets.sort_by(|a, b| a.total_cmp(b));
+    onsets.dedup_by(|later, earlier| *later - *earlier <= CHORD_TOLERANCE);
+    let mut result: Vec<Chord> = vec![];
+    for onset in onsets {
+        let sounding = events
+            .iter()
+            .filter(|e| e.onset <= onset + CHORD_TOLERANCE && e.end() > onset + CHORD_TOLERANCE)
+            .collect::<Vec<_>>();
+        let pitch_classes = sounding
+            .iter()
+            .map(|e| e.pitch % NUM_PITCH_CLASSES)
+            .collect::<BTreeSet<_>>();
+        let Some(lowest) = sounding.iter().map(|e| e.pitch).min() else {
+            continue;
+        };
+        if pitch_classes.len() < MIN_CHORD_PITCH_CLASSES {
+            continue;
+        }
+        let bass = lowest % NUM_PITCH_CLASSES;
+        if let Some((root, quality)) = Chord::recognize(&pitch_classes, bass) {
+            let end = sounding.iter().map(|e| e.end()).fold(onset, f64::max);
+            match result.last_mut() {
+                Some(last) if last.root == root && last.quality == quality && last.bass == bass => {
+                    last.duration = end - last.onset;
+                }
+                _ => result.push(Chord {
+                    onset,
+                    duration: end - onset,
+                    root,
+                    bass,
+                    quality,
+                }),
+            }
+        }
+    }
+    result
+}
@@ -0,0 +1,115 @@
+use std::collections::BTreeSet;
+
+use midi_note_recorder::Recording;
+
+use crate::{
+    note_events::{NOTE_OFF, NOTE_ON, channel_bytes, channel_msg},
+    recorder::{CONTROL_CHANGE, PEDAL_DOWN, SUSTAIN_PEDAL},
+};
+
+pub const PITCH_BEND: u8 = 0xE0;
+pub const MOD_WHEEL: u8 = 1;
+pub const PITCH_BEND_CENTER: i16 = 8192;
+const NUM_MIDI_CHANNELS: usize = 16;
+
+/// Delays each note-off that arrives while its channel's sustain pedal is down
+/// until the pedal comes up, or until the same key is struck again, so that
+/// note durations reflect how long notes actually sounded.
+pub fn sustained(recording: &Recording) -> Recording {
+    let mut result = Recording::default();
+    let mut pedal_down = [false; NUM_MIDI_CHANNELS];
+    let mut ringing = BTreeSet::new();
+    let mut last_time = 0.0;
+    for (time, msg) in recording.midi_queue() {
+        last_time = time;
+        match channel_bytes(&msg) {
+            Some((status, channel, control, value))
+                if status == CONTROL_CHANGE && control == SUSTAIN_PEDAL =>
+            {
+                pedal_down[channel as usize] = value >= PEDAL_DOWN;
+                if !pedal_down[channel as usize] {
+                    let released = ringing
+                        .iter()
+                        .filter(|(c, _)| *c == channel)
+                        .copied()
+                        .collect::<Vec<_>>();
+                    for (channel, pitch) in released {
+                        ringing.remove(&(channel, pitch));
+                        result.add_message(time, &channel_msg(NOTE_OFF, channel, pitch, 0));
+                    }
+                }
+                result.add_message(time, &msg);
+            }
+            Some((status, channel, pitch, velocity))
+                if status == NOTE_OFF || (status == NOTE_ON && velocity == 0) =>
+            {
+                if pedal_down[channel as usize] {
+                    ringing.insert((channel, pitch));
+                } else {
+                    result.add_message(time, &msg);
+                }
+            }
+            Some((status, channel, pitch, _)) if status == NOTE_ON => {
+                if ringing.remove(&(channel, pitch)) {
+                    result.add_message(time, &channel_msg(NOTE_OFF, channel, pitch, 0));
+                }
+                result.add_message(time, &msg);
+            }
+            _ => result.add_message(time, &msg),
+        }
+    }
+    for (channel, pitch) in ringing {
+        result.add_message(last_time, &channel_msg(NOTE_OFF, channel, pitch, 0));
+    }
+    result
+}
+
+/// Times at which the sustain pedal went down, each with the time it came back
+/// up, if it did.
+pub fn pedal_spans(recording: &Recording) -> Vec<(f64, Option<f64>)> {
+    let mut spans = vec![];
+    let mut down = None;
+    for (time, value) in controller_values(recording, SUSTAIN_PEDAL) {
+        match (down, value >= PEDAL_DOWN) {
+            (None, true) => down = Some(time),
+            (Some(start), false) => {
+                spans.push((start, Some(time)));
+                down = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = down {
+        spans.push((start, None));
+    }
+    spans
+}
+
+/// Every value sent for `control`, on any channel.
+pub fn controller_values(recording: &Recording, control: u8) -> Vec<(f64, u8)> {
+    recording
+        .midi_queue()
+        .iter()
+        .filter_map(|(time, msg)| match channel_bytes(msg) {
+            Some((status, _, c, value)) if status == CONTROL_CHANGE && c == control => {
+                Some((*time, value))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Every pitch bend, on any channel, relative to the center position.
+pub fn pitch_bends(recording: &Recording) -> Vec<(f64, i16)> {
+    recording
+        .midi_queue()
+        .iter()
+        .filter_map(|(time, msg)| match channel_bytes(msg) {
+            Some((status, _, lsb, msb)) if status == PITCH_BEND => Some((
+                *time,
+                (((msb as i16) << 7) | lsb as i16) - PITCH_BEND_CENTER,
+            )),
+            _ => None,
+        })
+        .collect()
+}
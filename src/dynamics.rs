@@ -0,0 +1,174 @@
+use std::fmt::Display;
+
+/// Velocities averaged together when deciding which dynamic level a passage is at.
+const SMOOTHING_WINDOW: usize = 3;
+/// Notes a new level must last before it earns its own marking.
+const MIN_LEVEL_NOTES: usize = 2;
+/// Notes a steady rise or fall must span to earn a hairpin.
+const MIN_HAIRPIN_NOTES: usize = 3;
+/// Total velocity change a hairpin must cover.
+const MIN_HAIRPIN_CHANGE: i16 = 12;
+
+/// Dynamic levels, softest first.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub enum Dynamic {
+    Pianississimo,
+    Pianissimo,
+    Piano,
+    MezzoPiano,
+    MezzoForte,
+    Forte,
+    Fortissimo,
+    Fortississimo,
+}
+
+impl Dynamic {
+    pub fn all() -> [Self; 8] {
+        [
+            Self::Pianississimo,
+            Self::Pianissimo,
+            Self::Piano,
+            Self::MezzoPiano,
+            Self::MezzoForte,
+            Self::Forte,
+            Self::Fortissimo,
+            Self::Fortississimo,
+        ]
+    }
+
+    /// Highest velocity belonging to each level.
+    fn max_velocity(&self) -> u8 {
+        match self {
+            Self::Pianississimo => 16,
+            Self::Pianissimo => 32,
+            Self::Piano => 48,
+            Self::MezzoPiano => 64,
+            Self::MezzoForte => 80,
+            Self::Forte => 96,
+            Self::Fortissimo => 112,
+            Self::Fortississimo => 127,
+        }
+    }
+
+    pub fn from_velocity(velocity: u8) -> Self {
+        Self::all()
+            .into_iter()
+            .find(|d| velocity <= d.max_velocity())
+            .unwrap_or(Self::Fortississimo)
+    }
+
+    pub fn abbreviation(&self) -> &'static str {
+        match self {
+            Self::Pianississimo => "ppp",
+            Self::Pianissimo => "pp",
+            Self::Piano => "p",
+            Self::MezzoPiano => "mp",
+            Self::MezzoForte => "mf",
+            Self::Forte => "f",
+            Self::Fortissimo => "ff",
+            Self::Fortississimo => "fff",
+        }
+    }
+
+    /// The marking spelled out in Unicode musical symbols, which Bravura draws.
+    pub fn glyphs(&self) -> String {
+        self.abbreviation()
+            .chars()
+            .map(|c| match c {
+                'p' => '\u{1d18f}',
+                'm' => '\u{1d190}',
+                _ => '\u{1d191}',
+            })
+            .collect()
+    }
+}
+
+impl Display for Dynamic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.abbreviation())
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Hairpin {
+    Crescendo,
+    Decrescendo,
+}
+
+/// Dynamic markings derived from the velocities of a sequence of notes.
+/// Indices refer to positions in that sequence.
+#[derive(Clone, Default, Debug)]
+pub struct DynamicMarks {
+    pub levels: Vec<(usize, Dynamic)>,
+    /// First and last note of each hairpin.
+    pub hairpins: Vec<(usize, usize, Hairpin)>,
+}
+
+impl DynamicMarks {
+    pub fn from_velocities(velocities: &[u8]) -> Self {
+        Self {
+            levels: levels(velocities),
+            hairpins: hairpins(velocities),
+        }
+    }
+}
+
+fn smoothed(velocities: &[u8]) -> Vec<u8> {
+    (0..velocities.len())
+        .map(|i| {
+            let window = &velocities[i.saturating_sub(SMOOTHING_WINDOW / 2)
+                ..(i + SMOOTHING_WINDOW / 2 + 1).min(velocities.len())];
+            (window.iter().map(|v| *v as u16).sum::<u16>() / window.len() as u16) as u8
+        })
+        .collect()
+}
+
+/// Marks the opening level, then every level that holds for at least
+/// `MIN_LEVEL_NOTES` notes after changing.
+fn levels(velocities: &[u8]) -> Vec<(usize, Dynamic)> {
+    let dynamics = smoothed(velocities)
+        .into_iter()
+        .map(Dynamic::from_velocity)
+        .collect::<Vec<_>>();
+    let mut result: Vec<(usize, Dynamic)> = vec![];
+    let mut i = 0;
+    while i < dynamics.len() {
+        let run = dynamics[i..]
+            .iter()
+            .take_while(|d| **d == dynamics[i])
+            .count();
+        let current = result.last().map(|(_, d)| *d);
+        if current != Some(dynamics[i]) && (current.is_none() || run >= MIN_LEVEL_NOTES) {
+            result.push((i, dynamics[i]));
+        }
+        i += run;
+    }
+    result
+}
+
+/// Finds stretches where velocity keeps rising or keeps falling.
+fn hairpins(velocities: &[u8]) -> Vec<(usize, usize, Hairpin)> {
+    let mut result = vec![];
+    let mut start = 0;
+    while start + 1 < velocities.len() {
+        let rising = velocities[start + 1] > velocities[start];
+        let mut end = start + 1;
+        while end + 1 < velocities.len()
+            && velocities[end + 1] != velocities[end]
+            && (velocities[end + 1] > velocities[end]) == rising
+        {
+            end += 1;
+        }
+        let change = velocities[end] as i16 - velocities[start] as i16;
+        if end - start + 1 >= MIN_HAIRPIN_NOTES && change.abs() >= MIN_HAIRPIN_CHANGE {
+            let hairpin = if rising {
+                Hairpin::Crescendo
+            } else {
+                Hairpin::Decrescendo
+            };
+            result.push((start, end, hairpin));
+        }
+        start = end;
+    }
+    result
+}
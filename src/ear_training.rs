@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use music_analyzer_generator::{analyzer::Melody, generator::generate_melody_from};
+
+use crate::{
+    melody_diff::{NoteEdit, diff},
+    recorder::{Origin, Recorder, RecordingInfo},
+};
+
+/// Fraction of a melody's span by which an onset may drift before it earns no rhythm credit.
+const RHYTHM_TOLERANCE: f64 = 0.25;
+/// Pause between the end of the prompt and the start of the answer, in seconds.
+const ANSWER_GAP: f64 = 0.5;
+
+#[derive(Clone, Debug)]
+pub struct RoundScore {
+    pub answer: usize,
+    pub pitch_accuracy: f64,
+    pub rhythm_accuracy: f64,
+    /// Indices within `Melody::iter_direction()` of answer notes that do not match the prompt.
+    pub wrong_notes: Vec<usize>,
+}
+
+/// Call-and-response drill: the app plays a generated melody, the user plays
+/// it back, and the answer is scored against the prompt.
+#[derive(Default)]
+pub struct EarTraining {
+    prompt: Option<usize>,
+    rounds: Vec<RoundScore>,
+}
+
+impl EarTraining {
+    /// Generates a prompt from the recording at `seed`, adds it to `recorder`
+    /// and plays it. Returns `false` if no melody could be generated.
+    pub fn start_round(&mut self, recorder: &mut Recorder, seed: usize) -> bool {
+        match generate_melody_from(&Melody::from(&recorder[seed])) {
+            Some(prompt) => {
+                let duration = prompt.duration() as f64 + ANSWER_GAP;
+                let info = RecordingInfo {
+                    origin: Origin::Variation,
+                    parent: Some(seed),
+                    ..Default::default()
+                };
+                recorder.add_recording_with(prompt.into(), info);
+                let index = recorder.len() - 1;
+                recorder.start_solo(index, duration);
+                self.prompt = Some(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Scores the answer once the user has finished playing it; call once per
+    /// frame. A round whose answer phase ends with nothing played is abandoned.
+    pub fn update(&mut self, recorder: &Recorder) {
+        let Some(prompt) = self.prompt else {
+            return;
+        };
+        if recorder.actively_soloing() || recorder.answering() {
+            return;
+        }
+        let answer = (prompt + 1..recorder.len())
+            .rev()
+            .find(|i| recorder.info(*i).backing == Some(prompt));
+        if let Some(answer) = answer {
+            let target = Melody::from(&recorder[prompt]);
+            let played = Melody::from(&recorder[answer]);
+            self.rounds.push(score(answer, &target, &played));
+        }
+        self.prompt = None;
+    }
+
+    /// Ends the current round without scoring it.
+    pub fn cancel_round(&mut self, recorder: &mut Recorder) {
+        if self.prompt.take().is_some() {
+            recorder.cancel_solo();
+        }
+    }
+
+    /// Updates stored recording indices after recordings are deleted or moved.
+    pub fn relink<F: Fn(usize) -> Option<usize>>(&mut self, map: F) {
+        self.prompt = self.prompt.and_then(&map);
+        self.rounds.retain_mut(|round| match map(round.answer) {
+            Some(answer) => {
+                round.answer = answer;
+                true
+            }
+            None => false,
+        });
+    }
+
+    pub fn in_round(&self) -> bool {
+        self.prompt.is_some()
+    }
+
+    pub fn rounds(&self) -> &[RoundScore] {
+        &self.rounds
+    }
+
+    pub fn round_for(&self, answer: usize) -> Option<&RoundScore> {
+        self.rounds.iter().find(|r| r.answer == answer)
+    }
+
+    /// Mean pitch and rhythm accuracy over all rounds.
+    pub fn averages(&self) -> Option<(f64, f64)> {
+        if self.rounds.is_empty() {
+            None
+        } else {
+            let n = self.rounds.len() as f64;
+            Some((
+                self.rounds.iter().map(|r| r.pitch_accuracy).sum::<f64>() / n,
+                self.rounds.iter().map(|r| r.rhythm_accuracy).sum::<f64>() / n,
+            ))
+        }
+    }
+}
+
+/// Compares the notes of `played` to those of `target` after aligning them
+/// with `diff`, so that an extra or missing note costs only itself. Onsets are
+/// measured as fractions of each melody's span, so tempo differences are not penalized.
+pub fn score(answer: usize, target: &Melody, played: &Melody) -> RoundScore {
+    let edits = diff(target, played);
+    let target_onsets = onsets(target);
+    let played_onsets = onsets(played);
+    let credit = |from: usize, to: usize| {
+        let error = (played_onsets[&to] - target_onsets[&from]).abs();
+        (1.0 - error / RHYTHM_TOLERANCE).max(0.0)
+    };
+    let mut wrong_notes = vec![];
+    let mut pitch_hits = 0;
+    let mut rhythm_credit = 0.0;
+    for edit in edits.iter() {
+        match *edit {
+            NoteEdit::Same { from, to } | NoteEdit::DurationChanged { from, to } => {
+                pitch_hits += 1;
+                rhythm_credit += credit(from, to);
+            }
+            NoteEdit::PitchChanged { from, to } | NoteEdit::BothChanged { from, to } => {
+                wrong_notes.push(to);
+                rhythm_credit += credit(from, to);
+            }
+            NoteEdit::Inserted { to } => wrong_notes.push(to),
+            NoteEdit::Deleted { .. } => {}
+        }
+    }
+    let count = target_onsets.len().max(played_onsets.len()).max(1) as f64;
+    RoundScore {
+        answer,
+        pitch_accuracy: pitch_hits as f64 / count,
+        rhythm_accuracy: rhythm_credit / count,
+        wrong_notes,
+    }
+}
+
+/// Each sounding note's index and onset as a fraction of the melody's span.
+fn onsets(melody: &Melody) -> HashMap<usize, f64> {
+    let mut time = 0.0;
+    let mut result = vec![];
+    for (i, (note, _)) in melody.iter_direction().enumerate() {
+        if !note.is_rest() {
+            result.push((i, time));
+        }
+        time += note.duration() as f64;
+    }
+    if let Some((_, first)) = result.first().copied() {
+        let last = result.last().unwrap().1;
+        let span = if last > first { last - first } else { 1.0 };
+        for (_, onset) in result.iter_mut() {
+            *onset = (*onset - first) / span;
+        }
+    }
+    result.into_iter().collect()
+}
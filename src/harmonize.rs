@@ -0,0 +1,120 @@
+use midi_note_recorder::Recording;
+
+use crate::{
+    chords::NUM_PITCH_CLASSES,
+    keys::Key,
+    note_events::{NoteEvent, note_events, recording_from},
+    recorder::{free_channel, merge_recordings},
+    reduction::{Reduction, reduce},
+};
+
+const ACCOMPANIMENT_VELOCITY: u8 = 60;
+/// Lowest pitch a chord root is voiced at.
+const LOWEST_ROOT: u8 = 48;
+/// Arpeggio notes per beat.
+const ARPEGGIO_NOTES_PER_BEAT: f64 = 2.0;
+/// Order in which the degrees of a key are tried, favoring the primary triads.
+const DEGREE_PREFERENCE: [usize; 7] = [0, 3, 4, 5, 1, 2, 6];
+
+/// How each chord of the accompaniment is played.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum Accompaniment {
+    #[default]
+    BlockChords,
+    Arpeggio,
+}
+
+impl Accompaniment {
+    pub fn all() -> [Self; 2] {
+        [Self::BlockChords, Self::Arpeggio]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::BlockChords => "Block chords",
+            Self::Arpeggio => "Arpeggio",
+        }
+    }
+}
+
+/// The triads of `key`, each built by stacking every other tone of the key on
+/// a degree counted from its tonic, as pitch classes listed from the root up,
+/// in order of preference.
+fn triads(key: &Key) -> Vec<[u8; 3]> {
+    let tones = key.pitch_classes();
+    let n = tones.len();
+    DEGREE_PREFERENCE
+        .iter()
+        .map(|d| [tones[*d], tones[(d + 2) % n], tones[(d + 4) % n]])
+        .collect()
+}
+
+/// Voices `triad` in close position with its root in the octave above `LOWEST_ROOT`.
+fn voicing(triad: [u8; 3]) -> [u8; 3] {
+    let root = LOWEST_ROOT + triad[0];
+    let above = |pc: u8| root + (pc + NUM_PITCH_CLASSES - triad[0]) % NUM_PITCH_CLASSES;
+    [root, above(triad[1]), above(triad[2])]
+}
+
+/// Generates a chordal accompaniment for the melody of `recording` in `key`
+/// on a channel none of its notes use, and returns it merged with `recording`.
+/// Each melody note keeps the current chord if it fits, and otherwise moves to
+/// the most preferred triad containing it. Returns `None` if there are no
+/// notes or no channel is free.
+pub fn harmonize(
+    recording: &Recording,
+    key: &Key,
+    accompaniment: Accompaniment,
+    beats_per_minute: f64,
+) -> Option<Recording> {
+    let melody = reduce(&note_events(recording), Reduction::Highest).line;
+    let end = melody.iter().map(|e| e.end()).fold(0.0, f64::max);
+    let triads = triads(key);
+    let mut changes: Vec<(f64, [u8; 3])> = vec![];
+    for note in melody.iter() {
+        let pc = note.pitch % NUM_PITCH_CLASSES;
+        if changes.last().is_some_and(|(_, triad)| triad.contains(&pc)) {
+            continue;
+        }
+        if let Some(triad) = triads.iter().find(|triad| triad.contains(&pc)) {
+            changes.push((note.onset, *triad));
+        }
+    }
+    if changes.is_empty() {
+        return None;
+    }
+    let channel = free_channel(recording)?;
+
+    let mut events = vec![];
+    for (i, (onset, triad)) in changes.iter().enumerate() {
+        let until = changes.get(i + 1).map_or(end, |(next, _)| *next);
+        let pitches = voicing(*triad);
+        let note = |onset: f64, duration: f64, pitch: u8| NoteEvent {
+            onset,
+            duration,
+            pitch,
+            velocity: ACCOMPANIMENT_VELOCITY,
+            channel,
+        };
+        match accompaniment {
+            Accompaniment::BlockChords => {
+                for pitch in pitches {
+                    events.push(note(*onset, until - onset, pitch));
+                }
+            }
+            Accompaniment::Arpeggio => {
+                let step = 60.0 / beats_per_minute / ARPEGGIO_NOTES_PER_BEAT;
+                let pattern = [pitches[0], pitches[1], pitches[2], pitches[1]];
+                let mut time = *onset;
+                for pitch in pattern.iter().cycle() {
+                    if time >= until {
+                        break;
+                    }
+                    events.push(note(time, step.min(until - time), *pitch));
+                    time += step;
+                }
+            }
+        }
+    }
+    Some(merge_recordings(recording, &recording_from(&events)))
+}
@@ -0,0 +1,72 @@
+const MAX_HISTORY: usize = 100;
+
+/// Undo/redo stacks of described commands. Each command is stored as its
+/// inverse: the change that reverses it, which when applied yields the change
+/// that reverses it in turn, so undoing and redoing are symmetric.
+pub struct History<C> {
+    undo: Vec<(String, C)>,
+    redo: Vec<(String, C)>,
+    /// Counts every change to the stacks.
+    version: u64,
+}
+
+impl<C> Default for History<C> {
+    fn default() -> Self {
+        Self {
+            undo: vec![],
+            redo: vec![],
+            version: 0,
+        }
+    }
+}
+
+impl<C> History<C> {
+    /// Records a command that was just applied, given the change that reverses it.
+    pub fn record(&mut self, description: String, inverse: C) {
+        if self.undo.len() == MAX_HISTORY {
+            self.undo.remove(0);
+        }
+        self.undo.push((description, inverse));
+        self.redo.clear();
+        self.version += 1;
+    }
+
+    /// Forgets the most recent command, for one that turned out to change nothing.
+    pub fn cancel(&mut self) {
+        self.undo.pop();
+        self.version += 1;
+    }
+
+    /// A number that changes whenever a command is recorded, undone or redone,
+    /// to tell whether the most recent command is still the one just recorded.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Reverses the most recent command by passing its inverse to `apply`,
+    /// which returns the change that redoes it. Returns the command's description.
+    pub fn undo<F: FnOnce(C) -> C>(&mut self, apply: F) -> Option<String> {
+        let (description, inverse) = self.undo.pop()?;
+        self.redo.push((description.clone(), apply(inverse)));
+        self.version += 1;
+        Some(description)
+    }
+
+    /// Reapplies the most recently undone command, returning its description.
+    pub fn redo<F: FnOnce(C) -> C>(&mut self, apply: F) -> Option<String> {
+        let (description, change) = self.redo.pop()?;
+        self.undo.push((description.clone(), apply(change)));
+        self.version += 1;
+        Some(description)
+    }
+
+    /// Descriptions of undoable commands, oldest first.
+    pub fn undo_descriptions(&self) -> impl Iterator<Item = &str> {
+        self.undo.iter().map(|(d, _)| d.as_str())
+    }
+
+    /// Descriptions of redoable commands, next to redo first.
+    pub fn redo_descriptions(&self) -> impl Iterator<Item = &str> {
+        self.redo.iter().rev().map(|(d, _)| d.as_str())
+    }
+}
@@ -0,0 +1,156 @@
+use music_analyzer_generator::{
+    notes::NoteName,
+    scales::{RootedScale, ScaleMode},
+};
+
+use crate::{
+    chords::{MAJOR_STEPS, NUM_PITCH_CLASSES, SPELLING_OCTAVE, spell},
+    note_events::NoteEvent,
+};
+
+/// Degrees of the tonic triad within a mode's seven degrees.
+const TRIAD_DEGREES: [usize; 3] = [0, 2, 4];
+
+/// The modes of the major scale, in order of the major-scale degree each
+/// starts on.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum Mode {
+    #[default]
+    Major,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Minor,
+    Locrian,
+}
+
+impl Mode {
+    pub fn all() -> [Self; 7] {
+        [
+            Self::Major,
+            Self::Dorian,
+            Self::Phrygian,
+            Self::Lydian,
+            Self::Mixolydian,
+            Self::Minor,
+            Self::Locrian,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Major => "major",
+            Self::Dorian => "dorian",
+            Self::Phrygian => "phrygian",
+            Self::Lydian => "lydian",
+            Self::Mixolydian => "mixolydian",
+            Self::Minor => "minor",
+            Self::Locrian => "locrian",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::all().into_iter().find(|mode| mode.name() == name)
+    }
+
+    /// Whether the mode is one of the church modes rather than major or minor.
+    pub fn is_modal(&self) -> bool {
+        !matches!(self, Self::Major | Self::Minor)
+    }
+
+    /// The degree of the major scale the mode starts on, counting from 0.
+    fn degree(&self) -> usize {
+        Self::all().iter().position(|mode| mode == self).unwrap()
+    }
+
+    /// Semitones above the tonic of each of the mode's seven degrees.
+    pub fn steps(&self) -> [u8; 7] {
+        let degree = self.degree();
+        let start = MAJOR_STEPS[degree];
+        std::array::from_fn(|i| {
+            (MAJOR_STEPS[(degree + i) % MAJOR_STEPS.len()] + NUM_PITCH_CLASSES - start)
+                % NUM_PITCH_CLASSES
+        })
+    }
+}
+
+/// A tonic and the mode built on it, such as A minor or D dorian.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Key {
+    /// Pitch class of the tonic.
+    pub tonic: u8,
+    pub mode: Mode,
+}
+
+impl Key {
+    /// Pitch classes of the key's seven degrees, tonic first.
+    pub fn pitch_classes(&self) -> [u8; 7] {
+        self.mode
+            .steps()
+            .map(|step| (self.tonic + step) % NUM_PITCH_CLASSES)
+    }
+
+    /// The major scale with the same notes as the key, which gives its key
+    /// signature and note spellings.
+    pub fn scale(&self) -> RootedScale {
+        let offset = MAJOR_STEPS[self.mode.degree()];
+        major_scale((self.tonic + NUM_PITCH_CLASSES - offset) % NUM_PITCH_CLASSES)
+    }
+
+    /// The key's name, such as "F# minor".
+    pub fn name(&self) -> String {
+        format!("{} {}", spell(self.tonic, &self.scale()), self.mode.name())
+    }
+}
+
+/// A key that could fit a recording.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct KeyCandidate {
+    pub key: Key,
+    /// Fraction of the sounding time spent on notes of the key.
+    pub weight: f64,
+}
+
+/// The major scale on pitch class `tonic`.
+fn major_scale(tonic: u8) -> RootedScale {
+    ScaleMode::Major.rooted(NoteName::name_of(
+        SPELLING_OCTAVE + tonic % NUM_PITCH_CLASSES,
+    ))
+}
+
+/// Ranks every key of every mode by how well it fits `events`, best first.
+/// Keys that share their notes, such as C major and A minor, fit equally
+/// well, so they are told apart by the time spent on their tonic triad.
+pub fn key_candidates(events: &[NoteEvent]) -> Vec<KeyCandidate> {
+    let mut time_on = [0.0; NUM_PITCH_CLASSES as usize];
+    for event in events.iter() {
+        time_on[(event.pitch % NUM_PITCH_CLASSES) as usize] += event.duration;
+    }
+    let total = time_on.iter().sum::<f64>();
+    let mut ranked = Mode::all()
+        .into_iter()
+        .flat_map(|mode| (0..NUM_PITCH_CLASSES).map(move |tonic| Key { tonic, mode }))
+        .map(|key| {
+            let pitch_classes = key.pitch_classes();
+            let in_key = pitch_classes
+                .iter()
+                .map(|pc| time_on[*pc as usize])
+                .sum::<f64>();
+            let on_triad = TRIAD_DEGREES
+                .iter()
+                .map(|degree| time_on[pitch_classes[*degree] as usize])
+                .sum::<f64>();
+            let weight = if total > 0.0 { in_key / total } else { 0.0 };
+            (KeyCandidate { key, weight }, on_triad)
+        })
+        .collect::<Vec<_>>();
+    ranked.sort_by(|(a, a_triad), (b, b_triad)| {
+        b.weight
+            .total_cmp(&a.weight)
+            .then(b_triad.total_cmp(a_triad))
+            .then(a.key.mode.degree().cmp(&b.key.mode.degree()))
+            .then(a.key.tonic.cmp(&b.key.tonic))
+    });
+    ranked.into_iter().map(|(candidate, _)| candidate).collect()
+}
@@ -2,8 +2,23 @@ use std::path::PathBuf;
 
 use eframe::egui::{self, FontDefinitions};
 
+pub mod chords;
+pub mod controllers;
+pub mod dynamics;
+pub mod ear_training;
+pub mod harmonize;
+pub mod history;
+pub mod keys;
+pub mod melody_diff;
 pub mod melody_renderer;
+pub mod midi_messages;
+pub mod note_events;
+pub mod piano_roll;
 pub mod recorder;
+pub mod reduction;
+pub mod session;
+pub mod variation;
+pub mod voices;
 
 pub fn setup_font(filename: &str, cc: &eframe::CreationContext<'_>) -> anyhow::Result<()> {
     let manifest_dir = env!("CARGO_MANIFEST_DIR");
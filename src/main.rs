@@ -1,28 +1,74 @@
 use std::{
-    collections::VecDeque,
+    path::Path,
     sync::{Arc, Mutex},
 };
 
 use bare_metal_modulo::{MNum, ModNum};
 use crossbeam_utils::atomic::AtomicCell;
-use eframe::egui::{self, Color32, Pos2, Vec2, Visuals};
-use midi_fundsp::{
-    io::Speaker, note_velocity_from, sound_builders::ProgramTable, sounds::favorites,
-};
+use eframe::egui::{self, Color32, Modifiers, Pos2, Vec2, Visuals};
+use midi_fundsp::{io::Speaker, sound_builders::ProgramTable, sounds::favorites};
 use midi_melody_gui::{
-    melody_renderer::MelodyRenderer,
-    recorder::{Recorder, setup_threads},
-    render_synth_sounds, setup_font,
+    chords::chords,
+    controllers::{MOD_WHEEL, controller_values, pedal_spans, pitch_bends, sustained},
+    ear_training::EarTraining,
+    harmonize::{Accompaniment, harmonize},
+    keys::{Key, Mode, key_candidates},
+    melody_diff::{NoteEdit, diff, interval_changes},
+    melody_renderer::{DrawnNote, MelodyRenderer, NoteMarks, StaffResponse},
+    midi_messages::{MessageFilter, MessageKind, channel_of, describe},
+    note_events::{
+        MAX_VELOCITY, NoteEvent, NoteMessage, PairedNote, Pairing, edit_note, find_event,
+        note_events, note_message_errors, note_messages, paired_notes, recording_from,
+        sounding_events, with_note_messages,
+    },
+    piano_roll::{PianoRoll, TimeAxis},
+    recorder::{
+        Origin, RecordTrigger, Recorder, RecordingInfo, Segmentation, index_after_delete,
+        index_after_move, setup_threads,
+    },
+    reduction::{Reduced, Reduction, reduce},
+    render_synth_sounds,
+    session::{SESSION_EXTENSION, format_timestamp, load_session, save_session},
+    setup_font,
+    variation::{VariationParams, generate_variations},
+    voices::{DEFAULT_SPLIT_POINT, VoiceDisplay, voices},
 };
 use midi_msg::MidiMsg;
 use midi_note_recorder::Recording;
 use music_analyzer_generator::{
     analyzer::{Melody, MelodyDirection},
+    generator::generate_melody_from,
     scales::RootedScale,
 };
 
 const FPS: f32 = 20.0;
 const FRAME_INTERVAL: f32 = 1.0 / FPS;
+const DEFAULT_TRIGGER_PEDAL: u8 = 67;
+const MIDDLE_C: u8 = 60;
+const DEFAULT_SOLO_DURATION: f64 = 8.0;
+const BACKING_COLOR: Color32 = Color32::BLUE;
+const WRONG_NOTE_COLOR: Color32 = Color32::RED;
+const INSERTED_COLOR: Color32 = Color32::DARK_GREEN;
+const CHANGED_COLOR: Color32 = Color32::from_rgb(255, 140, 0);
+const DIFF_ORIGINAL_COLOR: Color32 = Color32::GRAY;
+const DISCARDED_COLOR: Color32 = Color32::LIGHT_GRAY;
+const DEFAULT_VELOCITY: u8 = 64;
+const MIN_VELOCITY_OPACITY: f32 = 0.25;
+const SELECTED_NOTE_COLOR: Color32 = Color32::from_rgb(0, 160, 220);
+const MAX_VARIATIONS: usize = 16;
+/// Key candidates listed for each of major, minor and the other modes.
+const KEY_CANDIDATES_PER_GROUP: usize = 12;
+const DEFAULT_SESSION_PATH: &str = "session.mmg";
+const BATCH_COLUMNS: usize = 3;
+const BATCH_STAFF_SIZE: Vec2 = Vec2 { x: 240.0, y: 160.0 };
+const OVERLAY_COLORS: [Color32; 6] = [
+    Color32::BLACK,
+    Color32::BLUE,
+    Color32::DARK_GREEN,
+    Color32::BROWN,
+    Color32::PURPLE,
+    Color32::from_rgb(255, 140, 0),
+];
 
 fn main() {
     let native_options = eframe::NativeOptions {
@@ -40,26 +86,139 @@ fn main() {
     .unwrap();
 }
 
+/// Ways of listing the events of the current recording.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum EventView {
+    NoteVelocity,
+    PairedNotes,
+    AllMessages,
+}
+
+impl EventView {
+    fn all() -> [Self; 3] {
+        [Self::NoteVelocity, Self::PairedNotes, Self::AllMessages]
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::NoteVelocity => "MIDI Note/Velocity only",
+            Self::PairedNotes => "Paired notes",
+            Self::AllMessages => "All MIDI messages",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum RecordingAction {
+    Duplicate,
+    MoveUp,
+    MoveDown,
+    Delete,
+    ClearAll,
+}
+
+impl RecordingAction {
+    fn all() -> [Self; 5] {
+        [
+            Self::Duplicate,
+            Self::MoveUp,
+            Self::MoveDown,
+            Self::Delete,
+            Self::ClearAll,
+        ]
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Duplicate => "Duplicate",
+            Self::MoveUp => "Move up",
+            Self::MoveDown => "Move down",
+            Self::Delete => "Delete",
+            Self::ClearAll => "Clear all",
+        }
+    }
+
+    fn applies_to(&self, index: usize, len: usize) -> bool {
+        match self {
+            Self::MoveUp => index > 0,
+            Self::MoveDown => index + 1 < len,
+            _ => true,
+        }
+    }
+}
+
 struct MainApp {
     recorder: Arc<Mutex<Recorder>>,
     synth_sounds: ProgramTable,
     synth_sound: usize,
     current_recording: ModNum<usize>,
-    show_note_velocity_only: bool,
+    event_view: EventView,
+    show_piano_roll: bool,
+    show_velocity: bool,
+    show_controllers: bool,
+    apply_sustain: bool,
+    voice_display: VoiceDisplay,
+    reduction: Option<Reduction>,
+    show_chords: bool,
+    accompaniment: Accompaniment,
+    harmonize_error: Option<String>,
+    show_discarded: bool,
+    time_axis: TimeAxis,
+    overdub: bool,
+    solo_duration: f64,
+    ear_training: EarTraining,
+    variation_params: VariationParams,
+    generation_error: Option<String>,
+    /// Recordings from the latest batch of variations, with whether to keep each.
+    batch: Vec<(usize, bool)>,
+    /// Recordings overlaid in the comparison view, with whether each is visible.
+    overlay: Vec<(usize, bool)>,
+    /// Recording that the current recording is compared against in the diff view.
+    diff_against: Option<usize>,
+    search: String,
+    new_tag: String,
+    /// Set from the first change to the name or notes until the field loses
+    /// focus, so that one edit is undone as a whole.
+    editing_details: bool,
+    session_path: String,
+    session_status: Option<String>,
+    confirm_clear: bool,
+    /// Note selected on the staff, with the recording it belongs to.
+    selected_note: Option<(usize, DrawnNote)>,
+    /// How far the selected note has been dragged so far.
+    note_drag: Vec2,
+    msg_filter: MessageFilter,
     playback_progress: Arc<AtomicCell<Option<f64>>>,
 }
 
 impl eframe::App for MainApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         ctx.set_visuals(Visuals::light());
+        self.import_dropped_files(ctx);
+        {
+            let mut recorder = self.recorder.lock().unwrap();
+            recorder.update();
+            self.ear_training.update(&recorder);
+        }
+        self.render_lineage(ctx);
+        self.render_progression(ctx);
         egui::CentralPanel::default().show(ctx, |ui| {
             let heading = format!("MIDI Melody GUI ({})", self.port_name());
             ui.heading(heading);
             ui.horizontal(|ui| {
                 self.render_settings(ui);
+                self.render_recording_controls(ui);
+                self.render_session(ui);
                 self.render_midi_instructions(ui);
             });
             self.render_melody_choice(ui);
+            self.render_details(ui);
+            self.render_key(ui);
+            self.render_history(ui);
+            self.render_ear_training(ui);
+            self.render_variation_panel(ui);
+            self.render_harmonizer(ui);
+            self.render_comparison_choice(ui);
             self.render_melody(ui);
             ctx.request_repaint_after_secs(FRAME_INTERVAL);
         });
@@ -75,7 +234,35 @@ impl MainApp {
             synth_sounds,
             synth_sound: 0,
             current_recording: ModNum::new(0, 1),
-            show_note_velocity_only: true,
+            event_view: EventView::NoteVelocity,
+            show_piano_roll: false,
+            show_velocity: false,
+            show_controllers: false,
+            apply_sustain: true,
+            voice_display: VoiceDisplay::default(),
+            reduction: None,
+            show_chords: false,
+            accompaniment: Accompaniment::default(),
+            harmonize_error: None,
+            show_discarded: true,
+            time_axis: TimeAxis::default(),
+            overdub: false,
+            solo_duration: DEFAULT_SOLO_DURATION,
+            ear_training: EarTraining::default(),
+            variation_params: VariationParams::default(),
+            generation_error: None,
+            batch: vec![],
+            overlay: vec![],
+            diff_against: None,
+            search: String::new(),
+            new_tag: String::new(),
+            editing_details: false,
+            session_path: DEFAULT_SESSION_PATH.to_owned(),
+            session_status: None,
+            confirm_clear: false,
+            selected_note: None,
+            note_drag: Vec2::ZERO,
+            msg_filter: MessageFilter::default(),
             playback_progress: Arc::new(AtomicCell::new(None)),
         })
     }
@@ -84,26 +271,412 @@ impl MainApp {
         self.recorder.lock().unwrap().input_port_name().to_string()
     }
 
-    fn render_melody_choice(&mut self, ui: &mut egui::Ui) {
+    fn render_lineage(&mut self, ctx: &egui::Context) {
+        let recorder = self.recorder.lock().unwrap();
+        if recorder.is_empty() {
+            return;
+        }
+        if recorder.len() != self.current_recording.m() {
+            self.current_recording = ModNum::new(recorder.len() - 1, recorder.len());
+        }
+        let mut children = vec![vec![]; recorder.len()];
+        let mut roots = vec![];
+        for index in 0..recorder.len() {
+            match recorder.info(index).parent {
+                Some(parent) if parent < recorder.len() => children[parent].push(index),
+                _ => roots.push(index),
+            }
+        }
+        let mut selected = self.current_recording.a();
+        let mut action = None;
+        egui::SidePanel::left("Lineage").show(ctx, |ui| {
+            ui.heading("Recordings");
+            ui.horizontal(|ui| {
+                ui.label("Search");
+                ui.text_edit_singleline(&mut self.search);
+            });
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                if self.search.is_empty() {
+                    for root in roots {
+                        Self::render_lineage_node(
+                            ui,
+                            &recorder,
+                            &children,
+                            root,
+                            &mut selected,
+                            &mut action,
+                        );
+                    }
+                } else {
+                    for index in 0..recorder.len() {
+                        let info = recorder.info(index);
+                        if info.matches(&self.search) {
+                            ui.selectable_value(&mut selected, index, info.label(index))
+                                .context_menu(|ui| {
+                                    Self::render_action_menu(ui, index, recorder.len(), &mut action)
+                                });
+                        }
+                    }
+                }
+            });
+        });
+        self.current_recording = ModNum::new(selected, recorder.len());
+        drop(recorder);
+        if let Some((index, action)) = action {
+            self.apply_action(index, action);
+        }
+    }
+
+    fn render_progression(&self, ctx: &egui::Context) {
+        let recorder = self.recorder.lock().unwrap();
+        if !self.show_chords || recorder.is_empty() {
+            return;
+        }
+        let recording = &recorder[self.current_recording.a()];
+        let scale = self.scale_of(&recorder, self.current_recording.a());
+        let key = self.key_of(&recorder, self.current_recording.a());
+        let progression = chords(&self.notes_of(recording));
+        egui::SidePanel::right("Progression").show(ctx, |ui| {
+            ui.heading("Progression");
+            if progression.is_empty() {
+                ui.weak("No chords recognized");
+                return;
+            }
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("Chord progression")
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for chord in progression.iter() {
+                            ui.label(format!("{:.2}", chord.onset));
+                            ui.label(chord.symbol(&scale));
+                            ui.label(chord.numeral(&key));
+                            ui.end_row();
+                        }
+                    });
+            });
+        });
+    }
+
+    fn render_lineage_node(
+        ui: &mut egui::Ui,
+        recorder: &Recorder,
+        children: &Vec<Vec<usize>>,
+        index: usize,
+        selected: &mut usize,
+        action: &mut Option<(usize, RecordingAction)>,
+    ) {
+        let info = recorder.info(index);
+        let label = format!("{} ({})", info.label(index), info.origin);
+        ui.selectable_value(selected, index, label)
+            .context_menu(|ui| Self::render_action_menu(ui, index, recorder.len(), action));
+        if !children[index].is_empty() {
+            ui.indent(index, |ui| {
+                for child in children[index].iter() {
+                    Self::render_lineage_node(ui, recorder, children, *child, selected, action);
+                }
+            });
+        }
+    }
+
+    fn render_action_menu(
+        ui: &mut egui::Ui,
+        index: usize,
+        len: usize,
+        action: &mut Option<(usize, RecordingAction)>,
+    ) {
+        for choice in RecordingAction::all() {
+            if choice != RecordingAction::ClearAll
+                && choice.applies_to(index, len)
+                && ui.button(choice.name()).clicked()
+            {
+                *action = Some((index, choice));
+                ui.close();
+            }
+        }
+    }
+
+    fn apply_action(&mut self, index: usize, action: RecordingAction) {
+        let len = {
+            let mut recorder = self.recorder.lock().unwrap();
+            match action {
+                RecordingAction::Duplicate => recorder.duplicate_recording(index),
+                RecordingAction::MoveUp => recorder.move_recording(index, index - 1),
+                RecordingAction::MoveDown => recorder.move_recording(index, index + 1),
+                RecordingAction::Delete => recorder.delete_recording(index),
+                RecordingAction::ClearAll => recorder.clear(),
+            }
+            recorder.len()
+        };
+        let selected = match action {
+            RecordingAction::Duplicate => len - 1,
+            RecordingAction::MoveUp => {
+                self.relink(|i| Some(index_after_move(index, index - 1, i)));
+                index - 1
+            }
+            RecordingAction::MoveDown => {
+                self.relink(|i| Some(index_after_move(index, index + 1, i)));
+                index + 1
+            }
+            RecordingAction::Delete => {
+                self.relink(|i| index_after_delete(index, i));
+                index.min(len.saturating_sub(1))
+            }
+            RecordingAction::ClearAll => {
+                self.relink(|_| None);
+                0
+            }
+        };
+        self.current_recording = ModNum::new(selected, len.max(1));
+    }
+
+    /// Updates every stored recording index after recordings are deleted or moved.
+    fn relink<F: Fn(usize) -> Option<usize>>(&mut self, map: F) {
+        let relink_pairs = |pairs: &mut Vec<(usize, bool)>| {
+            *pairs = pairs
+                .drain(..)
+                .filter_map(|(i, flag)| map(i).map(|i| (i, flag)))
+                .collect();
+        };
+        relink_pairs(&mut self.batch);
+        relink_pairs(&mut self.overlay);
+        self.diff_against = self.diff_against.and_then(&map);
+        self.ear_training.relink(&map);
+    }
+
+    fn render_details(&mut self, ui: &mut egui::Ui) {
         let mut recorder = self.recorder.lock().unwrap();
-        if recorder.len() > 1 {
-            if recorder.len() > self.current_recording.m() {
-                self.current_recording = ModNum::new(recorder.len() - 1, recorder.len());
+        if recorder.is_empty() {
+            return;
+        }
+        let index = self.current_recording.a();
+        let mut edits = vec![];
+        let mut removed_tag = None;
+        let mut added_tag = None;
+        let mut info = recorder.info(index).clone();
+        ui.collapsing("Details", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Name");
+                edits.push(ui.text_edit_singleline(&mut info.name));
+            });
+            ui.horizontal_wrapped(|ui| {
+                ui.label("Tags");
+                for (i, tag) in info.tags.iter().enumerate() {
+                    if ui.small_button(format!("{tag} \u{d7}")).clicked() {
+                        removed_tag = Some(i);
+                    }
+                }
+                let response = ui.text_edit_singleline(&mut self.new_tag);
+                let entered =
+                    response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if (entered || ui.button("Add tag").clicked()) && !self.new_tag.trim().is_empty() {
+                    added_tag = Some(self.new_tag.trim().replace(',', " "));
+                    self.new_tag.clear();
+                }
+            });
+            ui.label("Notes");
+            edits.push(ui.text_edit_multiline(&mut info.notes));
+            ui.label(format!("Created {} UTC", format_timestamp(info.created)));
+            if !info.port.is_empty() {
+                ui.label(format!("Recorded from {}", info.port));
             }
+        });
+        if edits.iter().any(|response| response.changed()) {
+            if !self.editing_details {
+                recorder.checkpoint_info(index, "Edit details");
+                self.editing_details = true;
+            }
+            *recorder.info_mut(index) = info;
+        }
+        if edits.iter().any(|response| response.lost_focus()) {
+            self.editing_details = false;
+        }
+        if let Some(i) = removed_tag {
+            recorder.checkpoint_info(index, "Remove tag");
+            recorder.info_mut(index).tags.remove(i);
+        }
+        if let Some(tag) = added_tag {
+            recorder.checkpoint_info(index, format!("Add tag {tag}"));
+            recorder.info_mut(index).tags.push(tag);
+        }
+    }
+
+    fn render_key(&mut self, ui: &mut egui::Ui) {
+        let mut recorder = self.recorder.lock().unwrap();
+        if recorder.is_empty() {
+            return;
+        }
+        let index = self.current_recording.a();
+        let pinned = recorder.info(index).key;
+        let candidates = key_candidates(&self.line_of(&recorder[index]).line);
+        let detected = candidates[0].key;
+        let mut choice = None;
+        ui.collapsing("Key", |ui| {
             ui.horizontal(|ui| {
-                if ui.button("<").clicked() {
-                    self.current_recording -= 1;
+                ui.label(format!("Detected {}", detected.name()));
+                match pinned {
+                    Some(key) => {
+                        ui.label(format!("Pinned {}", key.name()));
+                        if ui.button("Unpin").clicked() {
+                            choice = Some(None);
+                        }
+                    }
+                    None => {
+                        ui.weak("Click a candidate to pin it");
+                    }
                 }
-                ui.label(format!(
-                    "Recording {}/{}",
-                    self.current_recording.a() + 1,
-                    recorder.len()
-                ));
-                if ui.button(">").clicked() {
-                    self.current_recording += 1;
+            });
+            let groups: [(&str, fn(&Mode) -> bool); 3] = [
+                ("Major", |mode| *mode == Mode::Major),
+                ("Minor", |mode| *mode == Mode::Minor),
+                ("Modal", Mode::is_modal),
+            ];
+            ui.horizontal_top(|ui| {
+                for (heading, in_group) in groups {
+                    ui.vertical(|ui| {
+                        ui.strong(heading);
+                        egui::Grid::new(("Key candidates", heading))
+                            .num_columns(3)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                let ranked = candidates.iter().filter(|c| in_group(&c.key.mode));
+                                for candidate in ranked.take(KEY_CANDIDATES_PER_GROUP) {
+                                    let selected = pinned == Some(candidate.key);
+                                    if ui
+                                        .selectable_label(selected, candidate.key.name())
+                                        .clicked()
+                                        && !selected
+                                    {
+                                        choice = Some(Some(candidate.key));
+                                    }
+                                    ui.label(format!("{:.0}%", candidate.weight * 100.0));
+                                    ui.weak(if candidate.key == detected {
+                                        "detected"
+                                    } else {
+                                        ""
+                                    });
+                                    ui.end_row();
+                                }
+                            });
+                    });
+                }
+            });
+        });
+        if let Some(key) = choice {
+            recorder.checkpoint_info(
+                index,
+                match key {
+                    Some(key) => format!("Pin key {}", key.name()),
+                    None => "Unpin key".to_owned(),
+                },
+            );
+            recorder.info_mut(index).key = key;
+        }
+    }
+
+    fn render_history(&mut self, ui: &mut egui::Ui) {
+        let mut undo = false;
+        let mut redo = false;
+        {
+            let recorder = self.recorder.lock().unwrap();
+            ui.collapsing("History", |ui| {
+                ui.horizontal(|ui| {
+                    undo = ui.button("Undo").clicked();
+                    redo = ui.button("Redo").clicked();
+                });
+                for description in recorder.undo_descriptions() {
+                    ui.label(description);
+                }
+                for description in recorder.redo_descriptions() {
+                    ui.weak(description);
+                }
+            });
+        }
+        if !ui.ctx().wants_keyboard_input() {
+            ui.input_mut(|i| {
+                if i.consume_key(Modifiers::COMMAND | Modifiers::SHIFT, egui::Key::Z) {
+                    redo = true;
+                } else if i.consume_key(Modifiers::COMMAND, egui::Key::Z) {
+                    undo = true;
+                }
+            });
+        }
+        if undo || redo {
+            let len = {
+                let mut recorder = self.recorder.lock().unwrap();
+                if undo {
+                    recorder.undo();
+                } else {
+                    recorder.redo();
+                }
+                recorder.len()
+            };
+            self.batch.clear();
+            self.relink(|i| if i < len { Some(i) } else { None });
+            let current = self.current_recording.a().min(len.saturating_sub(1));
+            self.current_recording = ModNum::new(current, len.max(1));
+        }
+    }
+
+    fn render_session(&mut self, ui: &mut egui::Ui) {
+        ui.vertical(|ui| {
+            ui.label("Session");
+            ui.text_edit_singleline(&mut self.session_path);
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    let recorder = self.recorder.lock().unwrap();
+                    self.session_status = Some(
+                        match save_session(Path::new(&self.session_path), &recorder) {
+                            Ok(()) => format!("Saved {} recordings", recorder.len()),
+                            Err(e) => format!("Save failed: {e}"),
+                        },
+                    );
+                }
+                if ui.button("Load").clicked() {
+                    let path = self.session_path.clone();
+                    self.load(Path::new(&path), false);
                 }
             });
+            if let Some(status) = &self.session_status {
+                ui.label(status);
+            }
+        });
+    }
+
+    fn import_dropped_files(&mut self, ctx: &egui::Context) {
+        let dropped = ctx.input(|i| i.raw.dropped_files.clone());
+        for path in dropped.iter().filter_map(|f| f.path.as_ref()) {
+            if path.extension().is_some_and(|e| e == SESSION_EXTENSION) {
+                self.load(path, true);
+            } else {
+                self.session_status = Some(format!(
+                    "Skipped {}: not a .{SESSION_EXTENSION} session",
+                    path.display()
+                ));
+            }
         }
+    }
+
+    /// Appends the recordings of the session at `path`; imported ones without
+    /// a parent are marked as such.
+    fn load(&mut self, path: &Path, import: bool) {
+        self.session_status = Some(match load_session(path) {
+            Ok(mut loaded) => {
+                if import {
+                    for (_, info) in loaded.iter_mut().filter(|(_, i)| i.parent.is_none()) {
+                        info.origin = Origin::Imported;
+                    }
+                }
+                let count = loaded.len();
+                self.recorder.lock().unwrap().extend(loaded);
+                format!("Loaded {count} recordings from {}", path.display())
+            }
+            Err(e) => format!("Loading {} failed: {e}", path.display()),
+        });
+    }
+
+    fn render_melody_choice(&mut self, ui: &mut egui::Ui) {
+        let mut recorder = self.recorder.lock().unwrap();
         if recorder.len() > 0 {
             if ui.button("Playback").clicked() {
                 recorder.start_playback_thread(
@@ -111,23 +684,714 @@ impl MainApp {
                     self.playback_progress.clone(),
                 );
             }
+            ui.horizontal(|ui| {
+                if recorder.actively_soloing() {
+                    ui.label("Listen...");
+                } else if ui.button("Practice").clicked() {
+                    recorder.start_solo(self.current_recording.a(), self.solo_duration);
+                }
+                ui.add(
+                    egui::DragValue::new(&mut self.solo_duration)
+                        .range(1.0..=60.0)
+                        .suffix(" s backing"),
+                );
+            });
             if ui.button("Generate variation").clicked() {
-                let melody = Melody::from(&recorder[self.current_recording.a()]);
-                if let Some(variation) =
-                    music_analyzer_generator::generator::generate_melody_from(&melody)
+                let index = self.current_recording.a();
+                let melody = Melody::from(&recorder[index]);
+                match generate_melody_from(&melody) {
+                    Some(variation) => {
+                        self.generation_error = None;
+                        let info = RecordingInfo {
+                            origin: Origin::Variation,
+                            parent: Some(index),
+                            ..Default::default()
+                        };
+                        recorder.add_recording_with(variation.into(), info);
+                    }
+                    None => {
+                        self.generation_error =
+                            Some("Could not generate a melody from this recording".to_owned())
+                    }
+                }
+            }
+            if let Some(params) = recorder.info(self.current_recording.a()).generation {
+                ui.label(format!("Generated with {params}"));
+            }
+        }
+        let mut action = None;
+        if recorder.len() > 0 {
+            let index = self.current_recording.a();
+            ui.horizontal(|ui| {
+                for choice in RecordingAction::all() {
+                    if choice.applies_to(index, recorder.len()) {
+                        if choice == RecordingAction::ClearAll {
+                            if self.confirm_clear {
+                                ui.label("Delete every recording?");
+                                if ui.button("Yes").clicked() {
+                                    action = Some((index, choice));
+                                    self.confirm_clear = false;
+                                }
+                                if ui.button("No").clicked() {
+                                    self.confirm_clear = false;
+                                }
+                            } else if ui.button(choice.name()).clicked() {
+                                self.confirm_clear = true;
+                            }
+                        } else if ui.button(choice.name()).clicked() {
+                            action = Some((index, choice));
+                        }
+                    }
+                }
+            });
+        }
+        drop(recorder);
+        if let Some((index, action)) = action {
+            self.apply_action(index, action);
+        }
+    }
+
+    fn render_ear_training(&mut self, ui: &mut egui::Ui) {
+        let mut recorder = self.recorder.lock().unwrap();
+        if recorder.is_empty() {
+            return;
+        }
+        ui.horizontal(|ui| {
+            if self.ear_training.in_round() {
+                ui.label("Play back what you hear...");
+                if ui.button("Cancel round").clicked() {
+                    self.ear_training.cancel_round(&mut recorder);
+                }
+            } else if ui.button("Ear training round").clicked() {
+                if !self
+                    .ear_training
+                    .start_round(&mut recorder, self.current_recording.a())
                 {
-                    recorder.add_recording(variation.into());
+                    self.generation_error =
+                        Some("Could not generate a melody from this recording".to_owned());
+                }
+            }
+            if let Some(round) = self.ear_training.rounds().last() {
+                ui.label(format!(
+                    "Round {}: pitch {:.0}%, rhythm {:.0}%",
+                    self.ear_training.rounds().len(),
+                    round.pitch_accuracy * 100.0,
+                    round.rhythm_accuracy * 100.0
+                ));
+            }
+            if let Some((pitch, rhythm)) = self.ear_training.averages() {
+                ui.label(format!(
+                    "Average: pitch {:.0}%, rhythm {:.0}%",
+                    pitch * 100.0,
+                    rhythm * 100.0
+                ));
+            }
+        });
+    }
+
+    fn render_variation_panel(&mut self, ui: &mut egui::Ui) {
+        let mut recorder = self.recorder.lock().unwrap();
+        if recorder.is_empty() {
+            return;
+        }
+        let mut requested = false;
+        ui.collapsing("Generate variations", |ui| {
+            let params = &mut self.variation_params;
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut params.seed).prefix("Seed "));
+                if ui.button("New seed").clicked() {
+                    params.seed = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map_or(0, |d| d.as_nanos() as u64);
                 }
+                ui.add(
+                    egui::DragValue::new(&mut params.count)
+                        .range(1..=MAX_VARIATIONS)
+                        .prefix("Count "),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut params.max_leap)
+                        .range(1..=24)
+                        .prefix("Max leap "),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut params.keep_rhythm, "Keep rhythm");
+                ui.checkbox(&mut params.keep_contour, "Keep contour");
+                ui.checkbox(&mut params.stay_in_scale, "Stay in scale");
+            });
+            requested = ui.button("Generate").clicked();
+        });
+        if requested {
+            let scale = self.scale_of(&recorder, self.current_recording.a());
+            let source = &recorder[self.current_recording.a()];
+            let key = recorder.info(self.current_recording.a()).key;
+            match generate_variations(source, &scale, &self.variation_params) {
+                Ok(variations) => {
+                    self.generation_error = None;
+                    let batch = variations.len() > 1;
+                    for (variation, params) in variations {
+                        let info = RecordingInfo {
+                            origin: Origin::Variation,
+                            parent: Some(self.current_recording.a()),
+                            generation: Some(params),
+                            key,
+                            ..Default::default()
+                        };
+                        recorder.add_recording_with(variation, info);
+                        if batch {
+                            self.batch.push((recorder.len() - 1, true));
+                        }
+                    }
+                }
+                Err(e) => self.generation_error = Some(format!("Generation failed: {e}")),
+            }
+        }
+        if let Some(error) = &self.generation_error {
+            ui.colored_label(WRONG_NOTE_COLOR, error);
+        }
+        if !self.batch.is_empty() {
+            let discarded =
+                Self::render_batch(ui, &mut recorder, &mut self.batch, &self.playback_progress);
+            drop(recorder);
+            for index in discarded {
+                self.apply_action(index, RecordingAction::Delete);
             }
         }
     }
 
-    fn render_melody(&mut self, ui: &mut egui::Ui) {
+    fn render_harmonizer(&mut self, ui: &mut egui::Ui) {
+        let mut recorder = self.recorder.lock().unwrap();
+        if recorder.is_empty() {
+            return;
+        }
+        let mut requested = false;
+        ui.collapsing("Harmonize", |ui| {
+            ui.horizontal(|ui| {
+                for accompaniment in Accompaniment::all() {
+                    ui.radio_value(&mut self.accompaniment, accompaniment, accompaniment.name());
+                }
+                requested = ui.button("Harmonize and play").clicked();
+                if let Some(error) = &self.harmonize_error {
+                    ui.colored_label(WRONG_NOTE_COLOR, error);
+                }
+            });
+        });
+        if !requested {
+            return;
+        }
+        let index = self.current_recording.a();
+        let source = &recorder[index];
+        let key = self.key_of(&recorder, index);
+        let bpm = recorder.beats_per_minute;
+        match harmonize(source, &key, self.accompaniment, bpm) {
+            Some(harmonized) => {
+                self.harmonize_error = None;
+                let name = self.accompaniment.name().to_lowercase();
+                let info = RecordingInfo {
+                    origin: Origin::Transform(format!("harmonized, {name}")),
+                    parent: Some(index),
+                    key: recorder.info(index).key,
+                    ..Default::default()
+                };
+                recorder.add_recording_with(harmonized, info);
+                let added = recorder.len() - 1;
+                recorder.start_playback_thread(added, self.playback_progress.clone());
+            }
+            None => {
+                self.harmonize_error =
+                    Some("Nothing to harmonize, or no channel free for it".to_string())
+            }
+        }
+    }
+
+    fn render_batch(
+        ui: &mut egui::Ui,
+        recorder: &mut Recorder,
+        batch: &mut Vec<(usize, bool)>,
+        playback_progress: &Arc<AtomicCell<Option<f64>>>,
+    ) -> Vec<usize> {
+        egui::ScrollArea::vertical()
+            .id_salt("Variation batch")
+            .max_height(BATCH_STAFF_SIZE.y * 2.0)
+            .show(ui, |ui| {
+                egui::Grid::new("Variation batch grid").show(ui, |ui| {
+                    for (i, (index, keep)) in batch.iter_mut().enumerate() {
+                        ui.vertical(|ui| {
+                            ui.label(format!("Recording {}", *index + 1));
+                            ui.allocate_ui(BATCH_STAFF_SIZE, |ui| {
+                                let melody = Melody::from(&recorder[*index]);
+                                MelodyRenderer::render(ui, &vec![(melody, Color32::BLACK)]);
+                            });
+                            ui.horizontal(|ui| {
+                                if ui.button("Play").clicked() {
+                                    recorder
+                                        .start_playback_thread(*index, playback_progress.clone());
+                                }
+                                ui.checkbox(keep, "Keep");
+                            });
+                        });
+                        if (i + 1) % BATCH_COLUMNS == 0 {
+                            ui.end_row();
+                        }
+                    }
+                });
+            });
+        let mut discarded = vec![];
+        if ui.button("Discard unkept variations").clicked() {
+            discarded = batch
+                .drain(..)
+                .filter(|(_, keep)| !keep)
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>();
+            discarded.sort_unstable_by(|a, b| b.cmp(a));
+        }
+        discarded
+    }
+
+    fn render_comparison_choice(&mut self, ui: &mut egui::Ui) {
         let recorder = self.recorder.lock().unwrap();
-        if recorder.len() > 0 {
-            let melody = Melody::from(&recorder[self.current_recording.a()]);
-            MelodyRenderer::render(ui, &vec![(melody, Color32::BLACK)]);
+        self.overlay.retain(|(index, _)| *index < recorder.len());
+        if self.diff_against.is_some_and(|d| d >= recorder.len()) {
+            self.diff_against = None;
+        }
+        if recorder.len() < 2 {
+            return;
+        }
+        ui.collapsing("Compare recordings", |ui| {
+            egui::ComboBox::from_label("Diff current recording against")
+                .selected_text(
+                    self.diff_against
+                        .map_or("Nothing".to_owned(), |d| format!("Recording {}", d + 1)),
+                )
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.diff_against, None, "Nothing");
+                    for index in 0..recorder.len() {
+                        let label = format!("Recording {}", index + 1);
+                        ui.selectable_value(&mut self.diff_against, Some(index), label);
+                    }
+                });
+            ui.horizontal_wrapped(|ui| {
+                for index in 0..recorder.len() {
+                    let mut ticked = self.overlay.iter().any(|(i, _)| *i == index);
+                    if ui.checkbox(&mut ticked, format!("{}", index + 1)).changed() {
+                        if ticked {
+                            self.overlay.push((index, true));
+                        } else {
+                            self.overlay.retain(|(i, _)| *i != index);
+                        }
+                    }
+                }
+            });
+            for (k, (index, visible)) in self.overlay.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.checkbox(visible, "");
+                    ui.colored_label(
+                        OVERLAY_COLORS[k % OVERLAY_COLORS.len()],
+                        format!("\u{25a0} Recording {}", *index + 1),
+                    );
+                });
+            }
+        });
+    }
+
+    fn render_diff(ui: &mut egui::Ui, original: Melody, changed: Melody, key: Key) {
+        let edits = diff(&original, &changed);
+        let mut marks = NoteMarks::new();
+        let (mut inserted, mut deleted, mut changed_notes) = (0, 0, 0);
+        for edit in edits.iter() {
+            match edit {
+                NoteEdit::Same { .. } => {}
+                NoteEdit::Inserted { to } => {
+                    inserted += 1;
+                    marks.insert((0, *to), INSERTED_COLOR);
+                }
+                NoteEdit::Deleted { from } => {
+                    deleted += 1;
+                    marks.insert((1, *from), WRONG_NOTE_COLOR);
+                }
+                NoteEdit::PitchChanged { to, .. }
+                | NoteEdit::DurationChanged { to, .. }
+                | NoteEdit::BothChanged { to, .. } => {
+                    changed_notes += 1;
+                    marks.insert((0, *to), CHANGED_COLOR);
+                }
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.colored_label(INSERTED_COLOR, format!("{inserted} inserted"));
+            ui.colored_label(WRONG_NOTE_COLOR, format!("{deleted} deleted"));
+            ui.colored_label(CHANGED_COLOR, format!("{changed_notes} changed"));
+        });
+        egui::CollapsingHeader::new("Changed intervals").show(ui, |ui| {
+            for change in interval_changes(&original, &changed) {
+                ui.label(format!("{change}"));
+            }
+        });
+        let melodies = vec![(changed, Color32::BLACK), (original, DIFF_ORIGINAL_COLOR)];
+        Self::render_in_key(ui, &melodies, &marks, key);
+    }
+
+    fn render_melody(&mut self, ui: &mut egui::Ui) {
+        let mut recorder = self.recorder.lock().unwrap();
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.show_piano_roll, false, "Staff");
+            ui.radio_value(&mut self.show_piano_roll, true, "Piano roll");
+            if self.show_piano_roll {
+                ui.separator();
+                for axis in TimeAxis::all() {
+                    ui.radio_value(&mut self.time_axis, axis, axis.name());
+                }
+                ui.add(Self::tempo_drag(&mut recorder.beats_per_minute));
+            } else {
+                ui.separator();
+                ui.checkbox(&mut self.show_velocity, "Velocity and dynamics");
+                ui.checkbox(&mut self.show_controllers, "Pedal and controllers");
+                ui.checkbox(&mut self.show_chords, "Chords");
+                egui::ComboBox::from_label("Voices")
+                    .selected_text(self.voice_display.name())
+                    .show_ui(ui, |ui| {
+                        for display in VoiceDisplay::all() {
+                            ui.selectable_value(&mut self.voice_display, display, display.name());
+                        }
+                    });
+            }
+            ui.checkbox(&mut self.apply_sustain, "Apply sustain pedal");
+            egui::ComboBox::from_label("Melody line")
+                .selected_text(self.reduction.map_or("All notes", |r| r.name()))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.reduction, None, "All notes");
+                    for reduction in Reduction::all() {
+                        ui.selectable_value(&mut self.reduction, Some(reduction), reduction.name());
+                    }
+                });
+            if self.reduction.is_some() && !self.show_piano_roll {
+                ui.checkbox(&mut self.show_discarded, "Show discarded notes");
+            }
+        });
+        if self.show_piano_roll {
+            if recorder.len() > 0 {
+                let recording = &recorder[self.current_recording.a()];
+                let scale = self.scale_of(&recorder, self.current_recording.a());
+                PianoRoll::render(
+                    ui,
+                    &self.line_of(recording).line,
+                    &scale,
+                    self.time_axis,
+                    recorder.beats_per_minute,
+                );
+            }
+        } else if let Some(original) = self.diff_against.filter(|d| *d < recorder.len()) {
+            let current = self.current_recording.a();
+            let changed = self.melody_of(&recorder[current]);
+            let key = self.key_of(&recorder, current);
+            Self::render_diff(ui, self.melody_of(&recorder[original]), changed, key);
+        } else if !self.overlay.is_empty() {
+            let melodies = self
+                .overlay
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, visible))| *visible)
+                .map(|(k, (index, _))| {
+                    let color = OVERLAY_COLORS[k % OVERLAY_COLORS.len()];
+                    (self.melody_of(&recorder[*index]), color)
+                })
+                .collect::<Vec<_>>();
+            let key = self.key_of(&recorder, self.current_recording.a());
+            Self::render_in_key(ui, &melodies, &NoteMarks::new(), key);
+        } else if let Some(voices) = recorder
+            .get(self.current_recording.a())
+            .and_then(|recording| self.voice_melodies(recording))
+        {
+            let key = self.key_of(&recorder, self.current_recording.a());
+            Self::render_voices(ui, voices, self.voice_display, key);
+        } else if recorder.len() > 0 {
+            let current = self.current_recording.a();
+            let line = self.line_of(&recorder[current]);
+            let melody = Melody::from(&recording_from(&line.line));
+            let sounding = sounding_events(&melody, &line.line);
+            let mut melodies = vec![(melody, Color32::BLACK)];
+            if let Some(backing) = recorder.info(current).backing {
+                if backing < recorder.len() {
+                    ui.colored_label(BACKING_COLOR, format!("Backing: Recording {}", backing + 1));
+                    melodies.push((self.melody_of(&recorder[backing]), BACKING_COLOR));
+                }
+            }
+            let mut marks = NoteMarks::new();
+            if let Some(round) = self.ear_training.round_for(current) {
+                for i in round.wrong_notes.iter() {
+                    marks.insert((0, *i), WRONG_NOTE_COLOR);
+                }
+            }
+            if let Some((recording, note)) = self.selected_note {
+                if recording == current {
+                    marks.insert((0, note.index), SELECTED_NOTE_COLOR);
+                }
+            }
+            let velocities = line.line.iter().map(|e| e.velocity).collect::<Vec<_>>();
+            if self.show_velocity {
+                Self::mark_velocities(&melodies[0].0, &sounding, &line.line, &mut marks);
+            }
+            ui.weak(
+                "Click a note to select it, drag it to change pitch or onset, Delete to remove it.",
+            );
+            let scale = self.scale_of(&recorder, current);
+            if let Some(staff) =
+                MelodyRenderer::render_editable(ui, &melodies, &marks, scale, &sounding)
+            {
+                let onsets = line.line.iter().map(|e| e.onset).collect::<Vec<_>>();
+                if self.show_discarded && !line.discarded.is_empty() {
+                    let notes = self.notes_of(&recorder[current]);
+                    let discarded = line
+                        .discarded
+                        .iter()
+                        .map(|i| (notes[*i].onset, notes[*i].pitch))
+                        .collect::<Vec<_>>();
+                    staff.render_notes_at(ui, &onsets, &discarded, DISCARDED_COLOR);
+                }
+                if self.show_chords {
+                    let scale = staff.scale();
+                    let symbols = chords(&self.notes_of(&recorder[current]))
+                        .iter()
+                        .map(|chord| (chord.onset, chord.symbol(scale)))
+                        .collect::<Vec<_>>();
+                    staff.render_chord_symbols(ui, &onsets, &symbols);
+                }
+                if self.show_controllers {
+                    let recording = &recorder[current];
+                    staff.render_pedal_marks(ui, &onsets, &pedal_spans(recording));
+                    staff.render_controller_lane(
+                        ui,
+                        &onsets,
+                        &controller_values(recording, MOD_WHEEL),
+                        &pitch_bends(recording),
+                    );
+                }
+                if self.show_velocity {
+                    staff.render_velocity_lane(ui, &velocities, Color32::BLACK);
+                }
+                if let Some((edited, description)) = Self::edit_notes(
+                    ui,
+                    &staff,
+                    current,
+                    &recorder[current],
+                    &line.line,
+                    &mut self.selected_note,
+                    &mut self.note_drag,
+                ) {
+                    recorder.set_recording(current, edited, description);
+                }
+            }
+        }
+    }
+
+    /// The melody of `recording`, with note durations extended by the sustain
+    /// pedal and reduced to a single line if those are enabled.
+    fn melody_of(&self, recording: &Recording) -> Melody {
+        if self.reduction.is_some() {
+            Melody::from(&recording_from(&self.line_of(recording).line))
+        } else if self.apply_sustain {
+            Melody::from(&sustained(recording))
+        } else {
+            Melody::from(recording)
+        }
+    }
+
+    /// The scale recording `index` is shown in: that of the key it is
+    /// analyzed in, so that the staff and the key panel agree.
+    fn scale_of(&self, recorder: &Recorder, index: usize) -> RootedScale {
+        self.key_of(recorder, index).scale()
+    }
+
+    /// The key recording `index` is analyzed in: the key pinned for it, if
+    /// any, and otherwise the candidate that best fits its melody line.
+    fn key_of(&self, recorder: &Recorder, index: usize) -> Key {
+        recorder
+            .info(index)
+            .key
+            .unwrap_or_else(|| key_candidates(&self.line_of(&recorder[index]).line)[0].key)
+    }
+
+    /// The notes of `recording`, with durations extended by the sustain pedal
+    /// if that is enabled.
+    fn notes_of(&self, recording: &Recording) -> Vec<NoteEvent> {
+        if self.apply_sustain {
+            note_events(&sustained(recording))
+        } else {
+            note_events(recording)
+        }
+    }
+
+    /// The notes of `recording` that form its melody line, indexed as in
+    /// `notes_of`.
+    fn line_of(&self, recording: &Recording) -> Reduced {
+        let notes = self.notes_of(recording);
+        match self.reduction {
+            Some(reduction) => reduce(&notes, reduction),
+            None => Reduced {
+                kept: (0..notes.len()).collect(),
+                line: notes,
+                discarded: vec![],
+            },
+        }
+    }
+
+    /// The melody of each channel of `recording`, if it has several and they
+    /// are to be shown apart.
+    fn voice_melodies(&self, recording: &Recording) -> Option<Vec<(u8, Melody)>> {
+        if self.voice_display == VoiceDisplay::Merged {
+            return None;
+        }
+        let voices = voices(recording);
+        if voices.len() < 2 {
+            return None;
+        }
+        Some(
+            voices
+                .into_iter()
+                .map(|(channel, voice)| (channel, self.melody_of(&voice)))
+                .collect(),
+        )
+    }
+
+    fn render_voices(
+        ui: &mut egui::Ui,
+        voices: Vec<(u8, Melody)>,
+        display: VoiceDisplay,
+        key: Key,
+    ) {
+        let colored = voices
+            .into_iter()
+            .enumerate()
+            .map(|(k, (channel, melody))| {
+                (channel, melody, OVERLAY_COLORS[k % OVERLAY_COLORS.len()])
+            })
+            .collect::<Vec<_>>();
+        if display == VoiceDisplay::Separate {
+            for (channel, melody, color) in colored {
+                ui.colored_label(color, format!("Channel {}", channel + 1));
+                Self::render_in_key(ui, &vec![(melody, color)], &NoteMarks::new(), key);
+            }
+        } else {
+            ui.horizontal(|ui| {
+                for (channel, _, color) in colored.iter() {
+                    ui.colored_label(*color, format!("\u{25a0} Channel {}", channel + 1));
+                }
+            });
+            let melodies = colored
+                .into_iter()
+                .map(|(_, melody, color)| (melody, color))
+                .collect::<Vec<_>>();
+            Self::render_in_key(ui, &melodies, &NoteMarks::new(), key);
+        }
+    }
+
+    /// Renders `melodies` in the scale of `key`.
+    fn render_in_key(
+        ui: &mut egui::Ui,
+        melodies: &Vec<(Melody, Color32)>,
+        marks: &NoteMarks,
+        key: Key,
+    ) {
+        MelodyRenderer::render_in_scale(ui, melodies, marks, key.scale());
+    }
+
+    /// Fades each unmarked note of `melody` in proportion to the velocity of
+    /// the event behind it, where `sounding` gives the event behind each
+    /// sounding note, as found by `sounding_events`.
+    fn mark_velocities(
+        melody: &Melody,
+        sounding: &[Option<usize>],
+        events: &[NoteEvent],
+        marks: &mut NoteMarks,
+    ) {
+        let notes = melody
+            .iter_direction()
+            .enumerate()
+            .filter(|(_, (note, _))| !note.is_rest())
+            .map(|(n, _)| n);
+        for (n, event) in notes.zip(sounding.iter()) {
+            let Some(event) = event.and_then(|e| events.get(e)) else {
+                continue;
+            };
+            let opacity = MIN_VELOCITY_OPACITY
+                + (1.0 - MIN_VELOCITY_OPACITY) * event.velocity as f32 / MAX_VELOCITY;
+            marks
+                .entry((0, n))
+                .or_insert(Color32::BLACK.gamma_multiply(opacity));
+        }
+    }
+
+    /// Applies clicks, drags and key presses on `staff` to `events`, the notes
+    /// of recording `current`. `drawn` is the list the staff's first melody
+    /// was built from, which each note's event indexes; its notes are found
+    /// in `events` by onset, pitch and channel. Returns a description of the
+    /// edit, if any.
+    fn edit_notes(
+        ui: &mut egui::Ui,
+        staff: &StaffResponse,
+        current: usize,
+        recording: &Recording,
+        drawn: &[NoteEvent],
+        selected_note: &mut Option<(usize, DrawnNote)>,
+        note_drag: &mut Vec2,
+    ) -> Option<(Recording, &'static str)> {
+        let response = &staff.response;
+        if response.clicked() || response.drag_started() {
+            let pressed = ui.input(|i| i.pointer.press_origin());
+            *selected_note = pressed
+                .or(response.interact_pointer_pos())
+                .and_then(|pos| staff.note_at(pos, 0))
+                .map(|note| (current, note));
+            *note_drag = Vec2::ZERO;
+        }
+        let (selected_in, note) = (*selected_note)?;
+        let events = note_events(recording);
+        let Some(event) = note
+            .event
+            .and_then(|i| drawn.get(i))
+            .and_then(|drawn| find_event(&events, drawn))
+            .filter(|_| selected_in == current)
+        else {
+            *selected_note = None;
+            return None;
+        };
+
+        let typing = ui.memory(|m| m.focused().is_some());
+        if !typing
+            && ui.input_mut(|i| {
+                i.consume_key(Modifiers::NONE, egui::Key::Delete)
+                    || i.consume_key(Modifiers::NONE, egui::Key::Backspace)
+            })
+        {
+            *selected_note = None;
+            return Some((edit_note(recording, &events[event], None), "Delete note"));
+        }
+
+        if let Some(delta) = response.total_drag_delta() {
+            *note_drag = delta;
+            let target = note.pos + delta;
+            ui.painter_at(response.rect).circle_stroke(
+                target,
+                staff.y_per_pitch(),
+                (1.0, SELECTED_NOTE_COLOR),
+            );
         }
+        if response.drag_stopped() && *note_drag != Vec2::ZERO {
+            let drag = std::mem::replace(note_drag, Vec2::ZERO);
+            let start = events.iter().map(|e| e.onset).fold(f64::MAX, f64::min);
+            let end = events.iter().map(|e| e.end()).fold(0.0, f64::max);
+            let mut moved = events[event];
+            if drag.y.abs() >= staff.y_per_pitch() / 2.0 {
+                moved.pitch = staff.pitch_at(note.pos.y + drag.y);
+            }
+            let shift = staff.duration_fraction(drag.x) as f64 * (end - start);
+            moved.onset = (moved.onset + shift).max(0.0);
+            *selected_note = None;
+            let edited = edit_note(recording, &events[event], Some(&moved));
+            return Some((edited, "Move note"));
+        }
+        None
     }
 
     fn render_settings(&mut self, ui: &mut egui::Ui) {
@@ -146,72 +1410,303 @@ impl MainApp {
         }
     }
 
+    fn render_recording_controls(&mut self, ui: &mut egui::Ui) {
+        let mut recorder = self.recorder.lock().unwrap();
+        ui.vertical(|ui| {
+            ui.label("Recording");
+            for segmentation in Segmentation::all() {
+                ui.radio_value(
+                    &mut recorder.segmentation,
+                    segmentation,
+                    segmentation.name(),
+                );
+            }
+            match recorder.segmentation {
+                Segmentation::Timeout => {
+                    ui.add(
+                        egui::DragValue::new(&mut recorder.timeout)
+                            .range(0.1..=30.0)
+                            .speed(0.1)
+                            .suffix(" s"),
+                    );
+                }
+                Segmentation::BeatsOfSilence => {
+                    ui.add(
+                        egui::DragValue::new(&mut recorder.silence_beats)
+                            .range(0.5..=32.0)
+                            .speed(0.5)
+                            .suffix(" beats"),
+                    );
+                    ui.add(Self::tempo_drag(&mut recorder.beats_per_minute));
+                }
+                _ => {}
+            }
+            if recorder.len() > 0 {
+                ui.checkbox(&mut self.overdub, "Overdub current recording");
+                if recorder.overdubbing() {
+                    ui.label("Overdubbing...");
+                } else if self.overdub
+                    && recorder.segmentation != Segmentation::Manual
+                    && ui.button("Record overdub").clicked()
+                {
+                    recorder.overdub = Some(self.current_recording.a());
+                    recorder.start_overdub();
+                }
+            }
+            recorder.overdub = if self.overdub {
+                Some(self.current_recording.a())
+            } else {
+                None
+            };
+            if recorder.segmentation == Segmentation::Manual {
+                if recorder.is_armed() {
+                    if ui.button("Stop").clicked() {
+                        recorder.stop();
+                    }
+                } else if ui.button("Record").clicked() {
+                    recorder.arm();
+                }
+                Self::render_trigger(ui, &mut recorder.trigger);
+            }
+            Self::render_split_point(ui, &mut recorder.split_point);
+        });
+    }
+
+    fn render_split_point(ui: &mut egui::Ui, split_point: &mut Option<u8>) {
+        let mut enabled = split_point.is_some();
+        ui.checkbox(&mut enabled, "Split keyboard");
+        if !enabled {
+            *split_point = None;
+            return;
+        }
+        let mut split = split_point.unwrap_or(DEFAULT_SPLIT_POINT);
+        ui.add(
+            egui::DragValue::new(&mut split)
+                .range(0..=127)
+                .prefix("lower voice below "),
+        );
+        *split_point = Some(split);
+    }
+
+    /// Edits the tempo used to split on beats of silence and to count beats
+    /// in the piano roll.
+    fn tempo_drag(beats_per_minute: &mut f64) -> egui::DragValue<'_> {
+        egui::DragValue::new(beats_per_minute)
+            .range(20.0..=300.0)
+            .suffix(" bpm")
+    }
+
+    fn render_trigger(ui: &mut egui::Ui, trigger: &mut Option<RecordTrigger>) {
+        let mut enabled = trigger.is_some();
+        ui.checkbox(&mut enabled, "MIDI trigger");
+        if !enabled {
+            *trigger = None;
+            return;
+        }
+        let mut current = trigger.unwrap_or(RecordTrigger::Pedal(DEFAULT_TRIGGER_PEDAL));
+        ui.horizontal(|ui| {
+            let toggled = ui.button(current.name()).clicked();
+            match &mut current {
+                RecordTrigger::Pedal(control) => {
+                    ui.add(egui::DragValue::new(control).range(0..=127).prefix("CC "))
+                }
+                RecordTrigger::Note(pitch) => ui.add(egui::DragValue::new(pitch).range(0..=127)),
+            };
+            if toggled {
+                current = match current {
+                    RecordTrigger::Pedal(_) => RecordTrigger::Note(MIDDLE_C),
+                    RecordTrigger::Note(_) => RecordTrigger::Pedal(DEFAULT_TRIGGER_PEDAL),
+                };
+            }
+        });
+        *trigger = Some(current);
+    }
+
     fn render_midi_instructions(&mut self, ui: &mut egui::Ui) {
-        let recorder = self.recorder.lock().unwrap();
+        let mut recorder = self.recorder.lock().unwrap();
         if recorder.len() > 0 {
-            let recording = &recorder[self.current_recording.a()];
-            let melody = Melody::from(recording);
-            let scale = melody.highest_weight_scale();
+            let index = self.current_recording.a();
+            let recording = &recorder[index];
+            let scale = self.scale_of(&recorder, index);
+            let mut edit = None;
             ui.vertical(|ui| {
-                ui.radio_value(
-                    &mut self.show_note_velocity_only,
-                    true,
-                    "MIDI Note/Velocity only",
-                );
-                ui.radio_value(
-                    &mut self.show_note_velocity_only,
-                    false,
-                    "All MIDI messages",
-                );
-                if self.show_note_velocity_only {
-                    Self::midi_note_velocity_chart(ui, &scale, recording);
-                } else {
-                    Self::full_midi_msgs_chart(ui, recording);
+                for view in EventView::all() {
+                    ui.radio_value(&mut self.event_view, view, view.name());
+                }
+                match self.event_view {
+                    EventView::NoteVelocity => {
+                        edit = Self::midi_note_velocity_chart(ui, &scale, recording);
+                    }
+                    EventView::PairedNotes => Self::paired_notes_chart(ui, &scale, recording),
+                    EventView::AllMessages => {
+                        Self::full_midi_msgs_chart(ui, &scale, recording, &mut self.msg_filter)
+                    }
                 }
             });
+            if let Some((checkpoint, messages)) = edit {
+                if checkpoint {
+                    recorder.checkpoint_recording(index, "Edit MIDI events");
+                }
+                let edited = with_note_messages(&recorder[index], &messages);
+                recorder.revise_recording(index, edited);
+            }
         }
     }
 
-    fn midi_note_velocity_chart(ui: &mut egui::Ui, scale: &RootedScale, recording: &Recording) {
+    /// Shows the note messages of `recording` for editing. Returns the edited
+    /// messages if anything changed, along with whether the edit begins a new
+    /// step in the undo history.
+    fn midi_note_velocity_chart(
+        ui: &mut egui::Ui,
+        scale: &RootedScale,
+        recording: &Recording,
+    ) -> Option<(bool, Vec<NoteMessage>)> {
+        let mut messages = note_messages(recording);
+        let errors = note_message_errors(&messages);
+        let mut checkpoint = false;
+        let mut changed = false;
+        let mut inserted = None;
+        let mut deleted = None;
         egui::ScrollArea::vertical().show(ui, |ui| {
             egui::Grid::new("MIDI instructions")
-                .num_columns(4)
+                .num_columns(7)
                 .spacing((10.0, 4.0))
                 .striped(true)
                 .show(ui, |ui| {
                     ui.label("Timestamp");
+                    ui.label("Event");
                     ui.label("Pitch");
                     ui.label("Velocity");
                     ui.label("Note");
+                    ui.label("");
+                    ui.label("");
                     ui.end_row();
-                    Self::render_midi_instruction_rows(ui, &scale, recording.midi_queue());
+                    let mut last_pitch = None;
+                    for i in 0..messages.len() {
+                        let earliest = if i > 0 { messages[i - 1].time } else { 0.0 };
+                        let latest = messages.get(i + 1).map_or(f64::MAX, |m| m.time);
+                        let message = &mut messages[i];
+                        let lowest_velocity = if message.on { 1 } else { 0 };
+                        let responses = [
+                            ui.add(
+                                egui::DragValue::new(&mut message.time)
+                                    .range(earliest..=latest)
+                                    .speed(0.01)
+                                    .max_decimals(2),
+                            ),
+                            ui.selectable_label(message.on, if message.on { "On" } else { "Off" }),
+                            ui.add(egui::DragValue::new(&mut message.pitch).range(0..=127)),
+                            ui.add(
+                                egui::DragValue::new(&mut message.velocity)
+                                    .range(lowest_velocity..=127),
+                            ),
+                        ];
+                        if responses[1].clicked() {
+                            message.on = !message.on;
+                            message.velocity = if message.on { DEFAULT_VELOCITY } else { 0 };
+                        }
+                        for response in responses.iter() {
+                            checkpoint |= response.drag_started()
+                                || response.gained_focus()
+                                || response.clicked();
+                            changed |= response.changed() || response.clicked();
+                        }
+                        let direction = Self::pick_direction(message.pitch, last_pitch);
+                        let (name, _, accidental) = scale.matching_pitch(message.pitch, direction);
+                        let name = match accidental {
+                            Some(accidental) => format!("{}*", name.with_acc(accidental)),
+                            None => format!("{name}"),
+                        };
+                        match errors[i] {
+                            Some(error) => {
+                                ui.colored_label(WRONG_NOTE_COLOR, format!("{name} ({error})"))
+                            }
+                            None => ui.label(name),
+                        };
+                        if ui
+                            .small_button("+")
+                            .on_hover_text("Insert a copy of this event after it")
+                            .clicked()
+                        {
+                            inserted = Some(i);
+                        }
+                        if ui
+                            .small_button("\u{d7}")
+                            .on_hover_text("Delete this event")
+                            .clicked()
+                        {
+                            deleted = Some(i);
+                        }
+                        ui.end_row();
+                        last_pitch = Some(message.pitch);
+                    }
                 });
         });
+        if let Some(i) = inserted {
+            messages.insert(i + 1, messages[i]);
+        }
+        if let Some(i) = deleted {
+            messages.remove(i);
+        }
+        if inserted.is_some() || deleted.is_some() {
+            checkpoint = true;
+            changed = true;
+        }
+        if changed {
+            Some((checkpoint, messages))
+        } else {
+            None
+        }
     }
 
-    fn render_midi_instruction_rows(
-        ui: &mut egui::Ui,
-        scale: &RootedScale,
-        mut msgs: VecDeque<(f64, MidiMsg)>,
-    ) {
-        let mut last_pitch = None;
-        while let Some((time, msg)) = msgs.pop_front() {
-            if let Some((note, velocity)) = note_velocity_from(&msg) {
-                ui.label(format!("{time:.2}"));
-                ui.label(format!("{note}"));
-                ui.label(format!("{velocity}"));
-                let direction = Self::pick_direction(note, last_pitch);
-                let (name, _, accidental) = scale.matching_pitch(note, direction);
-                if let Some(accidental) = accidental {
-                    let name = name.with_acc(accidental);
-                    ui.label(format!("{name}*"));
-                } else {
-                    ui.label(format!("{name}"));
-                }
-                ui.end_row();
-                last_pitch = Some(note);
-            }
+    fn paired_notes_chart(ui: &mut egui::Ui, scale: &RootedScale, recording: &Recording) {
+        let notes = paired_notes(recording);
+        let errors = notes.iter().filter(|n| n.pairing.is_error()).count();
+        if errors > 0 {
+            ui.colored_label(
+                WRONG_NOTE_COLOR,
+                format!("{errors} unmatched note-ons or note-offs"),
+            );
         }
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("Paired notes")
+                .num_columns(7)
+                .spacing((10.0, 4.0))
+                .striped(true)
+                .show(ui, |ui| {
+                    for heading in [
+                        "Onset", "Duration", "Pitch", "Note", "Velocity", "Release", "",
+                    ] {
+                        ui.label(heading);
+                    }
+                    ui.end_row();
+                    let mut last_pitch = None;
+                    for PairedNote { note, pairing } in notes {
+                        let direction = Self::pick_direction(note.pitch, last_pitch);
+                        let (name, _, accidental) = scale.matching_pitch(note.pitch, direction);
+                        let name = match accidental {
+                            Some(accidental) => format!("{}*", name.with_acc(accidental)),
+                            None => format!("{name}"),
+                        };
+                        let optional =
+                            |value: Option<u8>| value.map_or("-".to_string(), |v| format!("{v}"));
+                        let unmatched_off = matches!(pairing, Pairing::UnmatchedOff { .. });
+                        ui.label(format!("{:.2}", note.onset));
+                        ui.label(if unmatched_off {
+                            "-".to_string()
+                        } else {
+                            format!("{:.2}", note.duration)
+                        });
+                        ui.label(format!("{}", note.pitch));
+                        ui.label(name);
+                        ui.label(optional((!unmatched_off).then_some(note.velocity)));
+                        ui.label(optional(pairing.release_velocity()));
+                        ui.colored_label(WRONG_NOTE_COLOR, pairing.description());
+                        ui.end_row();
+                        last_pitch = Some(note.pitch);
+                    }
+                });
+        });
     }
 
     fn pick_direction(pitch: u8, last_pitch: Option<u8>) -> MelodyDirection {
@@ -224,26 +1719,95 @@ impl MainApp {
         })
     }
 
-    fn full_midi_msgs_chart(ui: &mut egui::Ui, recording: &Recording) {
+    fn full_midi_msgs_chart(
+        ui: &mut egui::Ui,
+        scale: &RootedScale,
+        recording: &Recording,
+        filter: &mut MessageFilter,
+    ) {
+        Self::render_msg_filter(ui, filter);
+        let rows = recording
+            .midi_queue()
+            .into_iter()
+            .map(|(time, msg)| {
+                let description = describe(&msg, scale);
+                (time, msg, description)
+            })
+            .collect::<Vec<_>>();
+        let shown = rows
+            .iter()
+            .filter(|(time, msg, description)| filter.matches(*time, msg, description))
+            .count();
+        ui.weak(format!("Showing {shown} of {} messages", rows.len()));
         egui::ScrollArea::vertical().show(ui, |ui| {
             egui::Grid::new("MIDI instructions")
-                .num_columns(2)
+                .num_columns(3)
                 .spacing((10.0, 4.0))
                 .striped(true)
                 .show(ui, |ui| {
                     ui.label("Timestamp");
+                    ui.label("Channel");
                     ui.label("MIDI Message");
                     ui.end_row();
-                    Self::render_midi_msg_rows(ui, recording.midi_queue());
+                    Self::render_midi_msg_rows(ui, rows, filter);
+                });
+        });
+    }
+
+    fn render_msg_filter(ui: &mut egui::Ui, filter: &mut MessageFilter) {
+        ui.horizontal_wrapped(|ui| {
+            for kind in MessageKind::all() {
+                ui.checkbox(filter.shows_mut(kind), kind.name());
+            }
+        });
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Channel")
+                .selected_text(filter.channel.map_or("All".to_string(), |c| format!("{c}")))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut filter.channel, None, "All");
+                    for channel in 1..=16 {
+                        ui.selectable_value(
+                            &mut filter.channel,
+                            Some(channel),
+                            format!("{channel}"),
+                        );
+                    }
                 });
+            ui.checkbox(&mut filter.limit_time, "From");
+            ui.add_enabled(
+                filter.limit_time,
+                egui::DragValue::new(&mut filter.from)
+                    .range(0.0..=f64::MAX)
+                    .speed(0.1)
+                    .suffix(" s"),
+            );
+            ui.label("to");
+            ui.add_enabled(
+                filter.limit_time,
+                egui::DragValue::new(&mut filter.to)
+                    .range(0.0..=f64::MAX)
+                    .speed(0.1)
+                    .suffix(" s"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Search");
+            ui.text_edit_singleline(&mut filter.text);
         });
     }
 
-    fn render_midi_msg_rows(ui: &mut egui::Ui, mut msgs: VecDeque<(f64, MidiMsg)>) {
-        while let Some((time, msg)) = msgs.pop_front() {
-            ui.label(format!("{time:.2}"));
-            ui.label(format!("{msg:?}"));
-            ui.end_row();
+    fn render_midi_msg_rows(
+        ui: &mut egui::Ui,
+        rows: Vec<(f64, MidiMsg, String)>,
+        filter: &MessageFilter,
+    ) {
+        for (time, msg, description) in rows {
+            if filter.matches(time, &msg, &description) {
+                ui.label(format!("{time:.2}"));
+                ui.label(channel_of(&msg).map_or(String::new(), |c| format!("{c}")));
+                ui.label(description);
+                ui.end_row();
+            }
         }
     }
 }
@@ -0,0 +1,186 @@
+use std::fmt::Display;
+
+use music_analyzer_generator::analyzer::Melody;
+
+const INDEL_COST: u32 = 2;
+const PITCH_COST: u32 = 2;
+const DURATION_COST: u32 = 1;
+/// Relative difference beyond which two durations count as changed.
+const DURATION_TOLERANCE: f64 = 0.1;
+
+/// One step of the alignment between an original and a changed melody. Indices
+/// refer to notes within `Melody::iter_direction()`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum NoteEdit {
+    Same { from: usize, to: usize },
+    PitchChanged { from: usize, to: usize },
+    DurationChanged { from: usize, to: usize },
+    BothChanged { from: usize, to: usize },
+    Inserted { to: usize },
+    Deleted { from: usize },
+}
+
+impl NoteEdit {
+    pub fn from_index(&self) -> Option<usize> {
+        match self {
+            Self::Same { from, .. }
+            | Self::PitchChanged { from, .. }
+            | Self::DurationChanged { from, .. }
+            | Self::BothChanged { from, .. }
+            | Self::Deleted { from } => Some(*from),
+            Self::Inserted { .. } => None,
+        }
+    }
+
+    pub fn to_index(&self) -> Option<usize> {
+        match self {
+            Self::Same { to, .. }
+            | Self::PitchChanged { to, .. }
+            | Self::DurationChanged { to, .. }
+            | Self::BothChanged { to, .. }
+            | Self::Inserted { to } => Some(*to),
+            Self::Deleted { .. } => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct DiffNote {
+    index: usize,
+    pitch: u8,
+    duration: f64,
+}
+
+fn sounding_notes(melody: &Melody) -> Vec<DiffNote> {
+    melody
+        .iter_direction()
+        .enumerate()
+        .filter(|(_, (note, _))| !note.is_rest())
+        .map(|(index, (note, _))| DiffNote {
+            index,
+            pitch: note.pitch(),
+            duration: note.duration() as f64,
+        })
+        .collect()
+}
+
+fn durations_differ(a: f64, b: f64) -> bool {
+    (a - b).abs() > DURATION_TOLERANCE * a.max(b)
+}
+
+fn substitution(a: &DiffNote, b: &DiffNote) -> (u32, NoteEdit) {
+    let (from, to) = (a.index, b.index);
+    match (a.pitch != b.pitch, durations_differ(a.duration, b.duration)) {
+        (false, false) => (0, NoteEdit::Same { from, to }),
+        (true, false) => (PITCH_COST, NoteEdit::PitchChanged { from, to }),
+        (false, true) => (DURATION_COST, NoteEdit::DurationChanged { from, to }),
+        (true, true) => (
+            PITCH_COST + DURATION_COST,
+            NoteEdit::BothChanged { from, to },
+        ),
+    }
+}
+
+/// Aligns the sounding notes of `original` and `changed` with minimum edit cost.
+pub fn diff(original: &Melody, changed: &Melody) -> Vec<NoteEdit> {
+    let a = sounding_notes(original);
+    let b = sounding_notes(changed);
+    let mut cost = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in 0..=a.len() {
+        for j in 0..=b.len() {
+            cost[i][j] = if i == 0 {
+                j as u32 * INDEL_COST
+            } else if j == 0 {
+                i as u32 * INDEL_COST
+            } else {
+                (cost[i - 1][j - 1] + substitution(&a[i - 1], &b[j - 1]).0)
+                    .min(cost[i - 1][j] + INDEL_COST)
+                    .min(cost[i][j - 1] + INDEL_COST)
+            };
+        }
+    }
+    let mut edits = vec![];
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 || j > 0 {
+        if i > 0 && j > 0 {
+            let (sub_cost, edit) = substitution(&a[i - 1], &b[j - 1]);
+            if cost[i][j] == cost[i - 1][j - 1] + sub_cost {
+                edits.push(edit);
+                i -= 1;
+                j -= 1;
+                continue;
+            }
+        }
+        if i > 0 && cost[i][j] == cost[i - 1][j] + INDEL_COST {
+            edits.push(NoteEdit::Deleted {
+                from: a[i - 1].index,
+            });
+            i -= 1;
+        } else {
+            edits.push(NoteEdit::Inserted { to: b[j - 1].index });
+            j -= 1;
+        }
+    }
+    edits.reverse();
+    edits
+}
+
+/// A melodic interval between consecutive aligned notes that differs between the melodies.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct IntervalChange {
+    /// Position of the interval's first note among the changed melody's sounding notes.
+    pub position: usize,
+    pub from: i16,
+    pub to: i16,
+}
+
+impl Display for IntervalChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Notes {}-{}: {:+} became {:+} semitones",
+            self.position + 1,
+            self.position + 2,
+            self.from,
+            self.to
+        )
+    }
+}
+
+/// Compares the intervals between each pair of consecutive notes present in both melodies.
+pub fn interval_changes(original: &Melody, changed: &Melody) -> Vec<IntervalChange> {
+    let pitches = |melody: &Melody| {
+        melody
+            .iter_direction()
+            .map(|(note, _)| note.pitch() as i16)
+            .collect::<Vec<_>>()
+    };
+    let a = pitches(original);
+    let b = pitches(changed);
+    let to_position = |to: usize| {
+        changed
+            .iter_direction()
+            .take(to)
+            .filter(|(note, _)| !note.is_rest())
+            .count()
+    };
+    let pairs = diff(original, changed)
+        .iter()
+        .filter_map(|edit| edit.from_index().zip(edit.to_index()))
+        .collect::<Vec<_>>();
+    pairs
+        .windows(2)
+        .filter_map(|w| {
+            let (from, to) = (a[w[1].0] - a[w[0].0], b[w[1].1] - b[w[0].1]);
+            if from == to {
+                None
+            } else {
+                Some(IntervalChange {
+                    position: to_position(w[0].1),
+                    from,
+                    to,
+                })
+            }
+        })
+        .collect()
+}
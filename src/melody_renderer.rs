@@ -1,14 +1,14 @@
 use std::{
     cmp::{max, min},
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     ops::RangeInclusive,
 };
 
 use bare_metal_modulo::{MNum, OffsetNumC};
 use eframe::{
-    egui::{Painter, Sense, Ui},
+    egui::{Painter, Response, Sense, Ui},
     emath::Align2,
-    epaint::{Color32, FontFamily, FontId, Pos2, Stroke, Vec2},
+    epaint::{Color32, FontFamily, FontId, Pos2, Shape, Stroke, Vec2},
 };
 use music_analyzer_generator::{
     analyzer::{Melody, MelodyDirection},
@@ -16,6 +16,11 @@ use music_analyzer_generator::{
     scales::{RootedScale, ScaleMode},
 };
 
+use crate::{
+    dynamics::{DynamicMarks, Hairpin},
+    note_events::MAX_VELOCITY,
+};
+
 const Y_PER_PITCH: f32 = 5.28;
 const MIDDLE_C: u8 = 60;
 const STAFF_PITCH_WIDTH: u8 = 19;
@@ -34,6 +39,19 @@ const LINE_STROKE: Stroke = Stroke {
 const NUM_NOTES_ON_STAFF: usize = 11;
 const TREBLE_INITIAL_OFFSET: u8 = 3;
 const BASS_TO_TREBLE_OFFSET: u8 = 14;
+const VELOCITY_LANE_HEIGHT: f32 = 40.0;
+const DYNAMICS_ROW_HEIGHT: f32 = 30.0;
+const DYNAMICS_FONT_SIZE: f32 = 24.0;
+const HAIRPIN_OPENING: f32 = 10.0;
+const PEDAL_ROW_HEIGHT: f32 = 28.0;
+const PEDAL_FONT_SIZE: f32 = 22.0;
+const CONTROLLER_LANE_HEIGHT: f32 = 50.0;
+const CONTROLLER_LABEL_SIZE: f32 = 10.0;
+const MAX_CONTROLLER_VALUE: f32 = 127.0;
+const MAX_PITCH_BEND: f32 = 8192.0;
+const CHORD_SYMBOL_SIZE: f32 = 14.0;
+const MOD_WHEEL_COLOR: Color32 = Color32::DARK_GREEN;
+const PITCH_BEND_COLOR: Color32 = Color32::PURPLE;
 
 pub fn font_id(size: f32) -> FontId {
     FontId {
@@ -155,6 +173,10 @@ impl KeySignature {
     }
 }
 
+/// Colors that override individual notes, keyed by the index of the melody
+/// and the index of the note within `Melody::iter_direction()`.
+pub type NoteMarks = HashMap<(usize, usize), Color32>;
+
 /// Musical symbols are a very tricky issue. Here are resources I've used:
 /// * Font: [Bravura](https://github.com/steinbergmedia/bravura)
 /// * [Unicode for a few symbols](https://www.compart.com/en/unicode/block/U+2600)
@@ -192,10 +214,57 @@ impl MelodyRenderer {
     }
 
     pub fn render(ui: &mut Ui, melodies: &Vec<(Melody, Color32)>) {
+        Self::render_marked(ui, melodies, &NoteMarks::new());
+    }
+
+    pub fn render_marked(ui: &mut Ui, melodies: &Vec<(Melody, Color32)>, marks: &NoteMarks) {
+        Self::render_sensing(ui, melodies, marks, None, Sense::hover(), &[]);
+    }
+
+    /// Renders like `render_marked`, but with the key signature and note
+    /// spellings of `scale` rather than the scale detected in the first melody.
+    pub fn render_in_scale(
+        ui: &mut Ui,
+        melodies: &Vec<(Melody, Color32)>,
+        marks: &NoteMarks,
+        scale: RootedScale,
+    ) {
+        Self::render_sensing(ui, melodies, marks, Some(scale), Sense::hover(), &[]);
+    }
+
+    /// Renders like `render_in_scale`, but responds to clicks and drags, and
+    /// reports where each note was drawn so callers can edit them. `events`
+    /// gives the event behind each sounding note of the first melody, as
+    /// found by `sounding_events`.
+    pub fn render_editable(
+        ui: &mut Ui,
+        melodies: &Vec<(Melody, Color32)>,
+        marks: &NoteMarks,
+        scale: RootedScale,
+        events: &[Option<usize>],
+    ) -> Option<StaffResponse> {
+        Self::render_sensing(
+            ui,
+            melodies,
+            marks,
+            Some(scale),
+            Sense::click_and_drag(),
+            events,
+        )
+    }
+
+    fn render_sensing(
+        ui: &mut Ui,
+        melodies: &Vec<(Melody, Color32)>,
+        marks: &NoteMarks,
+        scale: Option<RootedScale>,
+        sense: Sense,
+        events: &[Option<usize>],
+    ) -> Option<StaffResponse> {
         if let Some((lo, hi)) = Self::min_max_pitches_from(melodies) {
-            let scale = melodies[0].0.highest_weight_scale();
+            let scale = scale.unwrap_or_else(|| melodies[0].0.highest_weight_scale());
             let (size, middle_c_steps, hi) = Self::size_mid_c_hi(ui, lo, hi, &scale);
-            let (response, painter) = ui.allocate_painter(size, Sense::hover());
+            let (response, painter) = ui.allocate_painter(size, sense);
             let sig = KeySignature::from(&scale);
             let y_border = Y_OFFSET + response.rect.min.y;
             let y_middle_c = y_border + Y_PER_PITCH * middle_c_steps as f32;
@@ -207,7 +276,18 @@ impl MelodyRenderer {
                 y_middle_c,
             };
             renderer.render_staves(&painter, hi, y_border);
-            renderer.render_melody(&painter, melodies);
+            let notes = renderer.render_melody(&painter, melodies, marks, events);
+            Some(StaffResponse {
+                response,
+                notes,
+                note_offset_x: renderer.note_offset_x(),
+                total_note_x: renderer.total_note_x(),
+                y_middle_c: renderer.y_middle_c,
+                y_per_pitch: renderer.y_per_pitch,
+                scale: renderer.scale,
+            })
+        } else {
+            None
         }
     }
 
@@ -232,10 +312,31 @@ impl MelodyRenderer {
         self.draw_staff(&painter, Clef::Bass, y_bass);
     }
 
-    fn render_melody(&self, painter: &Painter, melodies: &Vec<(Melody, Color32)>) {
-        for (melody, color) in melodies.iter().rev() {
-            self.draw_melody(&painter, melody, *color);
+    fn render_melody(
+        &self,
+        painter: &Painter,
+        melodies: &Vec<(Melody, Color32)>,
+        marks: &NoteMarks,
+        events: &[Option<usize>],
+    ) -> Vec<DrawnNote> {
+        let mut drawn = vec![];
+        for (m, (melody, color)) in melodies.iter().enumerate().rev() {
+            for (index, sounding, pos) in
+                self.draw_melody(&painter, melody, *color, |n| marks.get(&(m, n)).copied())
+            {
+                let event = match m {
+                    0 => events.get(sounding).copied().flatten(),
+                    _ => None,
+                };
+                drawn.push(DrawnNote {
+                    melody: m,
+                    index,
+                    event,
+                    pos,
+                });
+            }
         }
+        drawn
     }
 
     fn staff_line_space(&self) -> f32 {
@@ -260,17 +361,27 @@ impl MelodyRenderer {
         self.min_x() + X_OFFSET + KEY_SIGNATURE_OFFSET + self.y_per_pitch * self.sig.len() as f32
     }
 
-    fn draw_melody(&self, painter: &Painter, melody: &Melody, color: Color32) {
+    fn draw_melody<M: Fn(usize) -> Option<Color32>>(
+        &self,
+        painter: &Painter,
+        melody: &Melody,
+        color: Color32,
+        mark: M,
+    ) -> Vec<(usize, usize, Pos2)> {
+        let mut drawn = vec![];
         let mut note_renderer = IncrementalNoteRenderer::new(self, painter, color);
-        for (note, direction) in melody.iter_direction() {
+        for (n, (note, direction)) in melody.iter_direction().enumerate() {
             let x = self.note_offset_x()
                 + self.total_note_x() * note_renderer.total_duration / melody.duration() as f32;
             note_renderer.note_update(note, direction, &self.scale);
             let y = self.y_middle_c - note_renderer.staff_offset as f32 * self.y_per_pitch;
             if !note.is_rest() {
+                note_renderer.note_color = mark(n).unwrap_or(color);
                 note_renderer.show_note(x, y);
+                drawn.push((n, drawn.len(), Pos2 { x, y }));
             }
         }
+        drawn
     }
 
     fn draw_staff(&self, painter: &Painter, clef: Clef, start_y: f32) {
@@ -328,6 +439,325 @@ impl MelodyRenderer {
     }
 }
 
+/// A note as drawn on the staff.
+#[derive(Copy, Clone, Debug)]
+pub struct DrawnNote {
+    /// Index of the melody in the rendered list.
+    pub melody: usize,
+    /// Index of the note within `Melody::iter_direction()`.
+    pub index: usize,
+    /// Index of the event behind the note, for notes of the first melody of
+    /// an editable staff.
+    pub event: Option<usize>,
+    pub pos: Pos2,
+}
+
+/// The outcome of `MelodyRenderer::render_editable`: the staff's response and
+/// what is needed to map pointer positions back to notes, pitches and times.
+pub struct StaffResponse {
+    pub response: Response,
+    pub notes: Vec<DrawnNote>,
+    note_offset_x: f32,
+    total_note_x: f32,
+    y_middle_c: f32,
+    y_per_pitch: f32,
+    scale: RootedScale,
+}
+
+impl StaffResponse {
+    /// The note of `melody` drawn closest to `pos`, if any is within reach.
+    pub fn note_at(&self, pos: Pos2, melody: usize) -> Option<DrawnNote> {
+        self.notes
+            .iter()
+            .filter(|n| n.melody == melody && n.pos.distance(pos) <= self.y_per_pitch * 2.0)
+            .min_by(|a, b| a.pos.distance(pos).total_cmp(&b.pos.distance(pos)))
+            .copied()
+    }
+
+    /// The scale degree drawn nearest to height `y`.
+    pub fn pitch_at(&self, y: f32) -> u8 {
+        let offset = ((self.y_middle_c - y) / self.y_per_pitch).round() as i16;
+        (0..=127_u8)
+            .filter(|p| self.scale.contains(*p))
+            .min_by_key(|p| {
+                let (steps, _) = staff_position(&self.scale, *p, MelodyDirection::Ascending);
+                (steps - offset).abs()
+            })
+            .unwrap_or(MIDDLE_C)
+    }
+
+    /// Horizontal distance `dx` as a fraction of the melody's duration.
+    pub fn duration_fraction(&self, dx: f32) -> f32 {
+        dx / self.total_note_x
+    }
+
+    /// Horizontal position `x` as a fraction of the melody's duration.
+    pub fn time_fraction_at(&self, x: f32) -> f32 {
+        self.duration_fraction(x - self.note_offset_x)
+    }
+
+    pub fn y_per_pitch(&self) -> f32 {
+        self.y_per_pitch
+    }
+
+    /// The scale the staff was drawn in.
+    pub fn scale(&self) -> &RootedScale {
+        &self.scale
+    }
+
+    /// Maps `time` onto the staff's horizontal axis by interpolating between
+    /// the notes of the first melody, where `onsets` gives the onset of each
+    /// event behind them.
+    fn x_at(&self, onsets: &[f64], time: f64) -> f32 {
+        let mut points = self
+            .notes
+            .iter()
+            .filter(|n| n.melody == 0)
+            .filter_map(|n| Some((*onsets.get(n.event?)?, n.pos.x)))
+            .collect::<Vec<_>>();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let x = match points.iter().position(|(onset, _)| *onset >= time) {
+            _ if points.is_empty() => self.note_offset_x,
+            Some(0) => points[0].1,
+            Some(i) => {
+                let ((t1, x1), (t2, x2)) = (points[i - 1], points[i]);
+                if t2 > t1 {
+                    x1 + (x2 - x1) * ((time - t1) / (t2 - t1)) as f32
+                } else {
+                    x2
+                }
+            }
+            None => {
+                let ((t1, x1), (t2, x2)) = (points[0], points[points.len() - 1]);
+                if t2 > t1 {
+                    x2 + (x2 - x1) * ((time - t2) / (t2 - t1)) as f32
+                } else {
+                    x2
+                }
+            }
+        };
+        x.clamp(self.note_offset_x, self.response.rect.max.x)
+    }
+
+    /// Draws a note head for each `(time, pitch)` of `notes`, placed in time
+    /// against the first melody as in `x_at`, for notes left out of it.
+    pub fn render_notes_at(&self, ui: &Ui, onsets: &[f64], notes: &[(f64, u8)], color: Color32) {
+        let painter = ui.painter_at(self.response.rect);
+        for (time, pitch) in notes.iter() {
+            let (steps, _) = staff_position(&self.scale, *pitch, MelodyDirection::Ascending);
+            let pos = Pos2 {
+                x: self.x_at(onsets, *time),
+                y: self.y_middle_c - steps as f32 * self.y_per_pitch,
+            };
+            painter.circle_filled(pos, self.y_per_pitch, color);
+        }
+    }
+
+    /// Writes each of `symbols` above the treble staff at its time in seconds.
+    pub fn render_chord_symbols(&self, ui: &Ui, onsets: &[f64], symbols: &[(f64, String)]) {
+        let painter = ui.painter_at(self.response.rect);
+        let y = self.response.rect.min.y + BORDER_SIZE;
+        for (time, symbol) in symbols.iter() {
+            painter.text(
+                Pos2 {
+                    x: self.x_at(onsets, *time),
+                    y,
+                },
+                Align2::LEFT_TOP,
+                symbol,
+                font_id(CHORD_SYMBOL_SIZE),
+                Color32::BLACK,
+            );
+        }
+    }
+
+    /// Draws pedal markings beneath the bass staff for each span during
+    /// which the sustain pedal was held.
+    pub fn render_pedal_marks(&self, ui: &mut Ui, onsets: &[f64], spans: &[(f64, Option<f64>)]) {
+        let size = Vec2 {
+            x: self.response.rect.width(),
+            y: PEDAL_ROW_HEIGHT,
+        };
+        let (response, painter) = ui.allocate_painter(size, Sense::hover());
+        let y = response.rect.center().y;
+        for (down, up) in spans.iter() {
+            let x1 = self.x_at(onsets, *down);
+            let x2 = up.map_or(response.rect.max.x - BORDER_SIZE, |up| {
+                self.x_at(onsets, up)
+            });
+            painter.text(
+                Pos2 { x: x1, y },
+                Align2::LEFT_CENTER,
+                '\u{1d1ae}',
+                font_id(PEDAL_FONT_SIZE),
+                Color32::BLACK,
+            );
+            if up.is_some() {
+                painter.text(
+                    Pos2 { x: x2, y },
+                    Align2::CENTER_CENTER,
+                    '\u{1d1af}',
+                    font_id(PEDAL_FONT_SIZE),
+                    Color32::BLACK,
+                );
+            }
+        }
+    }
+
+    /// Draws mod wheel and pitch bend curves in a lane beneath the staff.
+    pub fn render_controller_lane(
+        &self,
+        ui: &mut Ui,
+        onsets: &[f64],
+        mod_wheel: &[(f64, u8)],
+        pitch_bends: &[(f64, i16)],
+    ) {
+        let size = Vec2 {
+            x: self.response.rect.width(),
+            y: CONTROLLER_LANE_HEIGHT,
+        };
+        let (response, painter) = ui.allocate_painter(size, Sense::hover());
+        let (top, bottom) = (response.rect.min.y, response.rect.max.y);
+        let middle = response.rect.center().y;
+        let end_x = response.rect.max.x - BORDER_SIZE;
+        painter.line_segment(
+            [
+                Pos2 {
+                    x: self.note_offset_x,
+                    y: middle,
+                },
+                Pos2 {
+                    x: end_x,
+                    y: middle,
+                },
+            ],
+            Stroke {
+                width: 0.5,
+                color: Color32::LIGHT_GRAY,
+            },
+        );
+        let label_x = response.rect.min.x + BORDER_SIZE;
+        for (text, y, color) in [
+            ("mod", top, MOD_WHEEL_COLOR),
+            ("bend", bottom, PITCH_BEND_COLOR),
+        ] {
+            let align = if y == top {
+                Align2::LEFT_TOP
+            } else {
+                Align2::LEFT_BOTTOM
+            };
+            painter.text(
+                Pos2 { x: label_x, y },
+                align,
+                text,
+                font_id(CONTROLLER_LABEL_SIZE),
+                color,
+            );
+        }
+
+        let mod_points = mod_wheel
+            .iter()
+            .map(|(time, value)| {
+                let y = bottom - CONTROLLER_LANE_HEIGHT * *value as f32 / MAX_CONTROLLER_VALUE;
+                (self.x_at(onsets, *time), y)
+            })
+            .collect::<Vec<_>>();
+        let bend_points = pitch_bends
+            .iter()
+            .map(|(time, bend)| {
+                let y = middle - CONTROLLER_LANE_HEIGHT / 2.0 * *bend as f32 / MAX_PITCH_BEND;
+                (self.x_at(onsets, *time), y)
+            })
+            .collect::<Vec<_>>();
+        for (points, color) in [
+            (mod_points, MOD_WHEEL_COLOR),
+            (bend_points, PITCH_BEND_COLOR),
+        ] {
+            painter.add(Shape::line(
+                step_curve(&points, end_x),
+                Stroke { width: 1.5, color },
+            ));
+        }
+    }
+
+    /// Draws a lane beneath the staff with one bar per note of the first
+    /// melody, where `velocities` gives the velocity of each event behind
+    /// them, followed by the dynamic markings and hairpins those velocities
+    /// suggest.
+    pub fn render_velocity_lane(&self, ui: &mut Ui, velocities: &[u8], color: Color32) {
+        let size = Vec2 {
+            x: self.response.rect.width(),
+            y: VELOCITY_LANE_HEIGHT + DYNAMICS_ROW_HEIGHT,
+        };
+        let (response, painter) = ui.allocate_painter(size, Sense::hover());
+        let base = response.rect.min.y + VELOCITY_LANE_HEIGHT;
+        painter.line_segment(
+            [
+                Pos2 {
+                    x: self.note_offset_x,
+                    y: base,
+                },
+                Pos2 {
+                    x: response.rect.max.x,
+                    y: base,
+                },
+            ],
+            LINE_STROKE,
+        );
+        let (xs, velocities): (Vec<f32>, Vec<u8>) = self
+            .notes
+            .iter()
+            .filter(|n| n.melody == 0)
+            .filter_map(|n| Some((n.pos.x, *velocities.get(n.event?)?)))
+            .unzip();
+        for (x, velocity) in xs.iter().zip(velocities.iter()) {
+            let height = VELOCITY_LANE_HEIGHT * *velocity as f32 / MAX_VELOCITY;
+            painter.line_segment(
+                [
+                    Pos2 { x: *x, y: base },
+                    Pos2 {
+                        x: *x,
+                        y: base - height,
+                    },
+                ],
+                Stroke {
+                    width: self.y_per_pitch,
+                    color,
+                },
+            );
+        }
+
+        let marks = DynamicMarks::from_velocities(&velocities);
+        let y = base + DYNAMICS_ROW_HEIGHT / 2.0;
+        for (i, dynamic) in marks.levels.iter() {
+            if let Some(x) = xs.get(*i) {
+                painter.text(
+                    Pos2 { x: *x, y },
+                    Align2::CENTER_CENTER,
+                    dynamic.glyphs(),
+                    font_id(DYNAMICS_FONT_SIZE),
+                    color,
+                );
+            }
+        }
+        for (start, end, hairpin) in marks.hairpins.iter() {
+            if let (Some(x1), Some(x2)) = (xs.get(*start), xs.get(*end)) {
+                let (x1, x2) = (x1 + self.y_per_pitch * 2.0, *x2);
+                let (point, open) = match hairpin {
+                    Hairpin::Crescendo => (x1, x2),
+                    Hairpin::Decrescendo => (x2, x1),
+                };
+                for dy in [-HAIRPIN_OPENING / 2.0, HAIRPIN_OPENING / 2.0] {
+                    painter.line_segment(
+                        [Pos2 { x: point, y }, Pos2 { x: open, y: y + dy }],
+                        Stroke { width: 1.0, color },
+                    );
+                }
+            }
+        }
+    }
+}
+
 struct IncrementalNoteRenderer<'a> {
     renderer: &'a MelodyRenderer,
     painter: &'a Painter,
@@ -369,6 +799,17 @@ impl<'a> IncrementalNoteRenderer<'a> {
     }
 }
 
+/// Holds each value until the next one arrives, and the last until `end_x`.
+fn step_curve(points: &[(f32, f32)], end_x: f32) -> Vec<Pos2> {
+    let mut curve = vec![];
+    for (i, (x, y)) in points.iter().enumerate() {
+        let next_x = points.get(i + 1).map_or(end_x, |(x, _)| *x);
+        curve.push(Pos2 { x: *x, y: *y });
+        curve.push(Pos2 { x: next_x, y: *y });
+    }
+    curve
+}
+
 fn staff_position(
     scale: &RootedScale,
     pitch: u8,
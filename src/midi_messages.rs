@@ -0,0 +1,196 @@
+use midi_msg::MidiMsg;
+use music_analyzer_generator::{analyzer::MelodyDirection, scales::RootedScale};
+
+use crate::{
+    controllers::{MOD_WHEEL, PITCH_BEND, PITCH_BEND_CENTER},
+    note_events::{CHANNEL_MASK, NOTE_OFF, NOTE_ON, STATUS_MASK},
+    recorder::{CONTROL_CHANGE, SUSTAIN_PEDAL},
+};
+
+const POLY_AFTERTOUCH: u8 = 0xA0;
+const PROGRAM_CHANGE: u8 = 0xC0;
+const CHANNEL_AFTERTOUCH: u8 = 0xD0;
+const SYSTEM_EXCLUSIVE: u8 = 0xF0;
+const TIMING_CLOCK: u8 = 0xF8;
+const START: u8 = 0xFA;
+const CONTINUE: u8 = 0xFB;
+const STOP: u8 = 0xFC;
+const ACTIVE_SENSING: u8 = 0xFE;
+
+/// Broad categories of MIDI messages, for filtering.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum MessageKind {
+    Note,
+    ControlChange,
+    ProgramChange,
+    PitchBend,
+    Clock,
+    ActiveSensing,
+    Other,
+}
+
+impl MessageKind {
+    pub const COUNT: usize = 7;
+
+    pub fn all() -> [Self; Self::COUNT] {
+        [
+            Self::Note,
+            Self::ControlChange,
+            Self::ProgramChange,
+            Self::PitchBend,
+            Self::Clock,
+            Self::ActiveSensing,
+            Self::Other,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Note => "Note",
+            Self::ControlChange => "CC",
+            Self::ProgramChange => "Program change",
+            Self::PitchBend => "Pitch bend",
+            Self::Clock => "Clock",
+            Self::ActiveSensing => "Active sensing",
+            Self::Other => "Other",
+        }
+    }
+
+    pub fn of(msg: &MidiMsg) -> Self {
+        match msg.to_midi().first() {
+            Some(status) if *status >= SYSTEM_EXCLUSIVE => match *status {
+                TIMING_CLOCK => Self::Clock,
+                ACTIVE_SENSING => Self::ActiveSensing,
+                _ => Self::Other,
+            },
+            Some(status) => match status & STATUS_MASK {
+                NOTE_ON | NOTE_OFF => Self::Note,
+                CONTROL_CHANGE => Self::ControlChange,
+                PROGRAM_CHANGE => Self::ProgramChange,
+                PITCH_BEND => Self::PitchBend,
+                _ => Self::Other,
+            },
+            None => Self::Other,
+        }
+    }
+}
+
+/// The channel of `msg`, counting from 1 as MIDI devices display it.
+pub fn channel_of(msg: &MidiMsg) -> Option<u8> {
+    match msg.to_midi().first() {
+        Some(status) if *status < SYSTEM_EXCLUSIVE => Some((status & CHANNEL_MASK) + 1),
+        _ => None,
+    }
+}
+
+/// A one-line description of `msg`, spelling note names within `scale`.
+pub fn describe(msg: &MidiMsg, scale: &RootedScale) -> String {
+    let bytes = msg.to_midi();
+    let data = |i: usize| bytes.get(i).copied().unwrap_or(0);
+    let Some(status) = bytes.first().copied() else {
+        return String::new();
+    };
+    if status >= SYSTEM_EXCLUSIVE {
+        return match status {
+            SYSTEM_EXCLUSIVE => format!("System exclusive ({} bytes)", bytes.len()),
+            TIMING_CLOCK => "Clock".to_string(),
+            START => "Start".to_string(),
+            CONTINUE => "Continue".to_string(),
+            STOP => "Stop".to_string(),
+            ACTIVE_SENSING => "Active sensing".to_string(),
+            _ => hex(&bytes),
+        };
+    }
+    match status & STATUS_MASK {
+        NOTE_ON if data(2) > 0 => {
+            format!("Note on {} velocity {}", note_name(data(1), scale), data(2))
+        }
+        NOTE_ON | NOTE_OFF => {
+            format!(
+                "Note off {} velocity {}",
+                note_name(data(1), scale),
+                data(2)
+            )
+        }
+        POLY_AFTERTOUCH => format!(
+            "Aftertouch {} pressure {}",
+            note_name(data(1), scale),
+            data(2)
+        ),
+        CONTROL_CHANGE => match data(1) {
+            SUSTAIN_PEDAL => format!("Sustain pedal {}", data(2)),
+            MOD_WHEEL => format!("Mod wheel {}", data(2)),
+            control => format!("Control {control} = {}", data(2)),
+        },
+        PROGRAM_CHANGE => format!("Program change {}", data(1)),
+        CHANNEL_AFTERTOUCH => format!("Channel pressure {}", data(1)),
+        PITCH_BEND => {
+            let bend = (((data(2) as i16) << 7) | data(1) as i16) - PITCH_BEND_CENTER;
+            format!("Pitch bend {bend:+}")
+        }
+        _ => hex(&bytes),
+    }
+}
+
+fn note_name(pitch: u8, scale: &RootedScale) -> String {
+    let (name, _, accidental) = scale.matching_pitch(pitch, MelodyDirection::Ascending);
+    let octave = pitch as i16 / 12 - 1;
+    match accidental {
+        Some(accidental) => format!("{}{octave}", name.with_acc(accidental)),
+        None => format!("{name}{octave}"),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Which messages the "All MIDI messages" table shows.
+#[derive(Clone, Debug)]
+pub struct MessageFilter {
+    pub kinds: [bool; MessageKind::COUNT],
+    /// Channel counting from 1, or `None` for all channels.
+    pub channel: Option<u8>,
+    pub limit_time: bool,
+    pub from: f64,
+    pub to: f64,
+    pub text: String,
+}
+
+impl Default for MessageFilter {
+    fn default() -> Self {
+        Self {
+            kinds: [true; MessageKind::COUNT],
+            channel: None,
+            limit_time: false,
+            from: 0.0,
+            to: 0.0,
+            text: String::new(),
+        }
+    }
+}
+
+impl MessageFilter {
+    pub fn shows(&self, kind: MessageKind) -> bool {
+        self.kinds[kind as usize]
+    }
+
+    pub fn shows_mut(&mut self, kind: MessageKind) -> &mut bool {
+        &mut self.kinds[kind as usize]
+    }
+
+    /// Whether a message at `time`, with the given `description`, passes.
+    pub fn matches(&self, time: f64, msg: &MidiMsg, description: &str) -> bool {
+        self.shows(MessageKind::of(msg))
+            && (self.channel.is_none() || channel_of(msg) == self.channel)
+            && (!self.limit_time || (self.from..=self.to).contains(&time))
+            && (self.text.is_empty()
+                || description
+                    .to_lowercase()
+                    .contains(&self.text.to_lowercase()))
+    }
+}
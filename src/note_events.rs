@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+
+use midi_msg::MidiMsg;
+use midi_note_recorder::Recording;
+use music_analyzer_generator::analyzer::Melody;
+
+use crate::recorder::merge_recordings;
+
+pub const NOTE_OFF: u8 = 0x80;
+pub const NOTE_ON: u8 = 0x90;
+pub const STATUS_MASK: u8 = 0xF0;
+pub const CHANNEL_MASK: u8 = 0x0F;
+/// Highest note velocity, as a float for scaling.
+pub const MAX_VELOCITY: f32 = 127.0;
+
+/// A note assembled from a note-on and its matching note-off.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct NoteEvent {
+    pub onset: f64,
+    pub duration: f64,
+    pub pitch: u8,
+    pub velocity: u8,
+    pub channel: u8,
+}
+
+impl NoteEvent {
+    pub fn end(&self) -> f64 {
+        self.onset + self.duration
+    }
+}
+
+/// Builds a channel message from its raw status nibble, channel and data bytes.
+pub fn channel_msg(status: u8, channel: u8, data1: u8, data2: u8) -> MidiMsg {
+    let bytes = [status | (channel & CHANNEL_MASK), data1, data2];
+    MidiMsg::from_midi(&bytes).unwrap().0
+}
+
+/// Returns the status nibble, channel and data bytes of a channel message.
+pub fn channel_bytes(msg: &MidiMsg) -> Option<(u8, u8, u8, u8)> {
+    let bytes = msg.to_midi();
+    if bytes.len() >= 3 && bytes[0] & 0x80 != 0 && bytes[0] < 0xF0 {
+        Some((
+            bytes[0] & STATUS_MASK,
+            bytes[0] & CHANNEL_MASK,
+            bytes[1],
+            bytes[2],
+        ))
+    } else {
+        None
+    }
+}
+
+/// Pairs each note-on in `recording` with the next note-off of the same pitch
+/// and channel. Notes never released last until the final message.
+pub fn note_events(recording: &Recording) -> Vec<NoteEvent> {
+    paired_notes(recording)
+        .into_iter()
+        .filter(|paired| !matches!(paired.pairing, Pairing::UnmatchedOff { .. }))
+        .map(|paired| paired.note)
+        .collect()
+}
+
+/// Renders `events` as note-on/note-off messages, releasing before striking at equal times.
+pub fn recording_from(events: &[NoteEvent]) -> Recording {
+    let mut msgs = vec![];
+    for event in events.iter() {
+        let on = channel_msg(NOTE_ON, event.channel, event.pitch, event.velocity);
+        let off = channel_msg(NOTE_OFF, event.channel, event.pitch, 0);
+        msgs.push((event.onset, 1, on));
+        msgs.push((event.end(), 0, off));
+    }
+    msgs.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+    let mut recording = Recording::default();
+    for (time, _, msg) in msgs {
+        recording.add_message(time, &msg);
+    }
+    recording
+}
+
+/// Moves `event`, one of the notes of `recording`, to the onset and pitch of
+/// `moved`, or deletes it if `moved` is `None`. Only the note's own note-on
+/// and, if it was released, note-off change; every other message is kept as
+/// it is.
+pub fn edit_note(recording: &Recording, event: &NoteEvent, moved: Option<&NoteEvent>) -> Recording {
+    let queue = recording.midi_queue();
+    let same_key = |msg: &MidiMsg| match channel_bytes(msg) {
+        Some((status, channel, pitch, velocity)) if status == NOTE_ON || status == NOTE_OFF => {
+            (channel == event.channel && pitch == event.pitch)
+                .then_some(status == NOTE_ON && velocity > 0)
+        }
+        _ => None,
+    };
+    let Some(start) = queue
+        .iter()
+        .position(|(time, msg)| *time == event.onset && same_key(msg) == Some(true))
+    else {
+        return recording.clone();
+    };
+    let end = queue
+        .iter()
+        .enumerate()
+        .skip(start + 1)
+        .find_map(|(i, (_, msg))| same_key(msg).map(|on| (i, on)))
+        .and_then(|(i, on)| (!on).then_some(i));
+    let mut messages = vec![];
+    for (i, (time, msg)) in queue.into_iter().enumerate() {
+        if i != start && Some(i) != end {
+            messages.push((time, msg));
+        } else if let Some(moved) = moved {
+            let (status, channel, _, velocity) = channel_bytes(&msg).unwrap();
+            let time = time + moved.onset - event.onset;
+            messages.push((time, channel_msg(status, channel, moved.pitch, velocity)));
+        }
+    }
+    messages.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut edited = Recording::default();
+    for (time, msg) in messages {
+        edited.add_message(time, &msg);
+    }
+    edited
+}
+
+/// For each sounding note of `melody`, built from `events`, the index of the
+/// event it came from: the unclaimed event of the same pitch whose onset, as
+/// a fraction of the span of onsets, is nearest. `Melody` merges and drops
+/// notes, so the two do not correspond position by position.
+pub fn sounding_events(melody: &Melody, events: &[NoteEvent]) -> Vec<Option<usize>> {
+    let mut time = 0.0;
+    let mut onsets = vec![];
+    for (note, _) in melody.iter_direction() {
+        if !note.is_rest() {
+            onsets.push((note.pitch(), time));
+        }
+        time += note.duration() as f64;
+    }
+    let melody_span = span(onsets.iter().map(|(_, onset)| *onset));
+    let event_span = span(events.iter().map(|e| e.onset));
+    let mut claimed = vec![false; events.len()];
+    onsets
+        .iter()
+        .map(|(pitch, onset)| {
+            let at = (onset - melody_span.0) / melody_span.1;
+            let nearest = events
+                .iter()
+                .enumerate()
+                .filter(|(i, e)| !claimed[*i] && e.pitch == *pitch)
+                .min_by(|(_, a), (_, b)| {
+                    let a = ((a.onset - event_span.0) / event_span.1 - at).abs();
+                    let b = ((b.onset - event_span.0) / event_span.1 - at).abs();
+                    a.total_cmp(&b)
+                })
+                .map(|(i, _)| i);
+            if let Some(i) = nearest {
+                claimed[i] = true;
+            }
+            nearest
+        })
+        .collect()
+}
+
+/// The earliest of `times` and the distance to the latest, or 1 if they coincide.
+fn span(times: impl Iterator<Item = f64>) -> (f64, f64) {
+    let (first, last) = times.fold((f64::MAX, f64::MIN), |(lo, hi), t| (lo.min(t), hi.max(t)));
+    (first, if last > first { last - first } else { 1.0 })
+}
+
+/// The index in `events` of the note with the same onset, pitch and channel
+/// as `event`, such as the unsustained original of a sustained note.
+pub fn find_event(events: &[NoteEvent], event: &NoteEvent) -> Option<usize> {
+    events.iter().position(|e| {
+        e.onset == event.onset && e.pitch == event.pitch && e.channel == event.channel
+    })
+}
+
+/// A single note-on or note-off message. A note-on with velocity 0 counts as
+/// a note-off; the velocity of a note-off is its release velocity.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct NoteMessage {
+    pub time: f64,
+    pub on: bool,
+    pub pitch: u8,
+    pub velocity: u8,
+    pub channel: u8,
+}
+
+impl NoteMessage {
+    pub fn msg(&self) -> MidiMsg {
+        let status = if self.on { NOTE_ON } else { NOTE_OFF };
+        channel_msg(status, self.channel, self.pitch, self.velocity)
+    }
+}
+
+/// The note-on and note-off messages of `recording`, in order.
+pub fn note_messages(recording: &Recording) -> Vec<NoteMessage> {
+    recording
+        .midi_queue()
+        .into_iter()
+        .filter_map(|(time, msg)| match channel_bytes(&msg) {
+            Some((status, channel, pitch, velocity)) if status == NOTE_ON || status == NOTE_OFF => {
+                Some(NoteMessage {
+                    time,
+                    on: status == NOTE_ON && velocity > 0,
+                    pitch,
+                    velocity,
+                    channel,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Replaces the note messages of `original` with `messages`, which must be in
+/// time order, keeping every other message.
+pub fn with_note_messages(original: &Recording, messages: &[NoteMessage]) -> Recording {
+    let mut notes = Recording::default();
+    for message in messages.iter() {
+        notes.add_message(message.time, &message.msg());
+    }
+    merge_recordings(&without_notes(original), &notes)
+}
+
+/// Describes what is wrong, if anything, with each of `messages`: note-offs
+/// with nothing to release, note-ons struck again before being released, and
+/// note-ons never released at all.
+pub fn note_message_errors(messages: &[NoteMessage]) -> Vec<Option<&'static str>> {
+    let mut errors = vec![None; messages.len()];
+    for (start, _, pairing) in pair_messages(messages) {
+        if pairing.is_error() {
+            errors[start] = Some(pairing.description());
+        }
+    }
+    errors
+}
+
+fn without_notes(original: &Recording) -> Recording {
+    let mut others = Recording::default();
+    for (time, msg) in original.midi_queue() {
+        match channel_bytes(&msg) {
+            Some((status, _, _, _)) if status == NOTE_ON || status == NOTE_OFF => {}
+            _ => others.add_message(time, &msg),
+        }
+    }
+    others
+}
+
+/// How a note's note-on and note-off were matched up.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Pairing {
+    Released {
+        release_velocity: u8,
+    },
+    /// Struck again before any note-off; ends where it was struck again.
+    Restruck,
+    /// No note-off at all; lasts until the final message.
+    Unreleased,
+    /// A note-off with no note-on before it; has no duration or velocity.
+    UnmatchedOff {
+        release_velocity: u8,
+    },
+}
+
+impl Pairing {
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Self::Released { .. })
+    }
+
+    pub fn release_velocity(&self) -> Option<u8> {
+        match self {
+            Self::Released { release_velocity } | Self::UnmatchedOff { release_velocity } => {
+                Some(*release_velocity)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Released { .. } => "",
+            Self::Restruck => "struck again before release",
+            Self::Unreleased => "never released",
+            Self::UnmatchedOff { .. } => "note-off without note-on",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PairedNote {
+    pub note: NoteEvent,
+    pub pairing: Pairing,
+}
+
+/// Like `note_events`, but keeps release velocities and reports note-ons and
+/// note-offs that lack a partner, in order of onset.
+pub fn paired_notes(recording: &Recording) -> Vec<PairedNote> {
+    let messages = note_messages(recording);
+    let last_time = recording.midi_queue().back().map_or(0.0, |(time, _)| *time);
+    let mut result = pair_messages(&messages)
+        .into_iter()
+        .map(|(start, end, pairing)| {
+            let start = messages[start];
+            let end = end.map_or(last_time, |end| messages[end].time);
+            let (velocity, end) = match pairing {
+                Pairing::UnmatchedOff { .. } => (0, start.time),
+                _ => (start.velocity, end),
+            };
+            PairedNote {
+                note: NoteEvent {
+                    onset: start.time,
+                    duration: end - start.time,
+                    pitch: start.pitch,
+                    velocity,
+                    channel: start.channel,
+                },
+                pairing,
+            }
+        })
+        .collect::<Vec<_>>();
+    result.sort_by(|a, b| {
+        a.note
+            .onset
+            .total_cmp(&b.note.onset)
+            .then(a.note.pitch.cmp(&b.note.pitch))
+    });
+    result
+}
+
+/// Matches each note-on in `messages` with the next message of the same pitch
+/// and channel. Yields, for each note, the index of the message that starts it,
+/// the index of the message that ends it if any, and how they were matched.
+/// A note-off with nothing to release starts a note of its own.
+fn pair_messages(messages: &[NoteMessage]) -> Vec<(usize, Option<usize>, Pairing)> {
+    let mut open = HashMap::new();
+    let mut result = vec![];
+    for (i, message) in messages.iter().enumerate() {
+        let key = (message.channel, message.pitch);
+        if message.on {
+            if let Some(on) = open.insert(key, i) {
+                result.push((on, Some(i), Pairing::Restruck));
+            }
+        } else {
+            let release_velocity = message.velocity;
+            match open.remove(&key) {
+                Some(on) => result.push((on, Some(i), Pairing::Released { release_velocity })),
+                None => result.push((i, None, Pairing::UnmatchedOff { release_velocity })),
+            }
+        }
+    }
+    result.extend(open.into_values().map(|on| (on, None, Pairing::Unreleased)));
+    result
+}
@@ -0,0 +1,151 @@
+use eframe::{
+    egui::{Sense, Ui},
+    emath::Align2,
+    epaint::{Color32, FontFamily, FontId, Pos2, Rect, Stroke, StrokeKind, Vec2},
+};
+use music_analyzer_generator::{analyzer::MelodyDirection, scales::RootedScale};
+
+use crate::note_events::{MAX_VELOCITY, NoteEvent};
+
+const ROW_HEIGHT: f32 = 10.0;
+const LABEL_WIDTH: f32 = 44.0;
+const AXIS_HEIGHT: f32 = 18.0;
+const BORDER_SIZE: f32 = 8.0;
+const MIN_NOTE_WIDTH: f32 = 2.0;
+const MIN_INTENSITY: f32 = 0.2;
+const LABEL_FONT_SIZE: f32 = 10.0;
+const NOTE_COLOR: Color32 = Color32::from_rgb(20, 60, 160);
+const OUT_OF_SCALE_ROW_COLOR: Color32 = Color32::from_rgb(232, 232, 232);
+const GRID_STROKE: Stroke = Stroke {
+    width: 0.5,
+    color: Color32::LIGHT_GRAY,
+};
+const NOTE_STROKE: Stroke = Stroke {
+    width: 0.5,
+    color: Color32::BLACK,
+};
+
+/// Units for the horizontal axis of the piano roll.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum TimeAxis {
+    #[default]
+    Seconds,
+    Beats,
+}
+
+impl TimeAxis {
+    pub fn all() -> [Self; 2] {
+        [Self::Seconds, Self::Beats]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Seconds => "Seconds",
+            Self::Beats => "Beats",
+        }
+    }
+}
+
+/// Draws notes as bars on a pitch-by-time grid. Rows outside the scale are
+/// shaded, and each bar's color intensity follows its velocity.
+pub struct PianoRoll {
+    hi: u8,
+    start: f64,
+    seconds_per_unit: f64,
+    units: f64,
+    rect: Rect,
+}
+
+impl PianoRoll {
+    pub fn render(
+        ui: &mut Ui,
+        events: &[NoteEvent],
+        scale: &RootedScale,
+        axis: TimeAxis,
+        beats_per_minute: f64,
+    ) {
+        let Some(lo) = events.iter().map(|e| e.pitch).min() else {
+            return;
+        };
+        let hi = events.iter().map(|e| e.pitch).max().unwrap();
+        let (lo, hi) = (lo.saturating_sub(1), hi.saturating_add(1).min(127));
+        let start = events.iter().map(|e| e.onset).fold(f64::MAX, f64::min);
+        let end = events.iter().map(|e| e.end()).fold(start, f64::max);
+        let seconds_per_unit = match axis {
+            TimeAxis::Seconds => 1.0,
+            TimeAxis::Beats => 60.0 / beats_per_minute,
+        };
+        let rows = (hi - lo + 1) as f32;
+        let size = Vec2 {
+            x: ui.available_width(),
+            y: rows * ROW_HEIGHT + AXIS_HEIGHT + BORDER_SIZE * 2.0,
+        };
+        let (response, painter) = ui.allocate_painter(size, Sense::hover());
+        let roll = Self {
+            hi,
+            start,
+            seconds_per_unit,
+            units: ((end - start) / seconds_per_unit).ceil().max(1.0),
+            rect: response.rect.shrink(BORDER_SIZE),
+        };
+
+        let font = FontId::new(LABEL_FONT_SIZE, FontFamily::Proportional);
+        for pitch in lo..=hi {
+            let row = roll.row_rect(pitch);
+            if !scale.contains(pitch) {
+                painter.rect_filled(row, 0.0, OUT_OF_SCALE_ROW_COLOR);
+            }
+            let (name, _, accidental) = scale.matching_pitch(pitch, MelodyDirection::Ascending);
+            let label = match accidental {
+                Some(accidental) => format!("{}", name.with_acc(accidental)),
+                None => format!("{name}"),
+            };
+            painter.text(
+                Pos2 {
+                    x: roll.rect.min.x,
+                    y: row.center().y,
+                },
+                Align2::LEFT_CENTER,
+                format!("{label}{}", pitch as i16 / 12 - 1),
+                font.clone(),
+                Color32::BLACK,
+            );
+            painter.hline(row.x_range(), row.max.y, GRID_STROKE);
+        }
+
+        let bottom = roll.rect.max.y - AXIS_HEIGHT;
+        for tick in 0..=roll.units as usize {
+            let x = roll.x(roll.start + tick as f64 * seconds_per_unit);
+            painter.vline(x, roll.rect.min.y..=bottom, GRID_STROKE);
+            painter.text(
+                Pos2 { x, y: bottom },
+                Align2::CENTER_TOP,
+                format!("{tick}"),
+                font.clone(),
+                Color32::BLACK,
+            );
+        }
+
+        for event in events.iter() {
+            let row = roll.row_rect(event.pitch);
+            let x1 = roll.x(event.onset);
+            let x2 = roll.x(event.end()).max(x1 + MIN_NOTE_WIDTH);
+            let bar = Rect::from_x_y_ranges(x1..=x2, row.y_range());
+            let intensity =
+                MIN_INTENSITY + (1.0 - MIN_INTENSITY) * event.velocity as f32 / MAX_VELOCITY;
+            painter.rect_filled(bar, 1.0, NOTE_COLOR.gamma_multiply(intensity));
+            painter.rect_stroke(bar, 1.0, NOTE_STROKE, StrokeKind::Inside);
+        }
+    }
+
+    fn x(&self, time: f64) -> f32 {
+        let width = self.rect.max.x - self.rect.min.x - LABEL_WIDTH;
+        let fraction = (time - self.start) / self.seconds_per_unit / self.units;
+        self.rect.min.x + LABEL_WIDTH + width * fraction as f32
+    }
+
+    fn row_rect(&self, pitch: u8) -> Rect {
+        let top = self.rect.min.y + (self.hi - pitch) as f32 * ROW_HEIGHT;
+        Rect::from_x_y_ranges(self.rect.min.x..=self.rect.max.x, top..=top + ROW_HEIGHT)
+    }
+}
@@ -5,14 +5,34 @@ use midi_fundsp::io::{
 };
 use midi_fundsp::note_velocity_from;
 use midi_fundsp::sound_builders::ProgramTable;
+use midi_msg::MidiMsg;
 use midi_note_recorder::Recording;
 use midir::MidiInput;
+
+use crate::{
+    history::History,
+    keys::Key,
+    note_events::{NOTE_OFF, NOTE_ON, channel_bytes, channel_msg, note_events},
+    variation::VariationParams,
+    voices::split_keyboard,
+};
+use std::collections::BTreeSet;
+use std::fmt::Display;
 use std::ops::Index;
 use std::sync::Mutex;
-use std::{sync::Arc, time::Instant};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
 
 pub const NUM_CHANNELS: usize = 10;
 pub const DEFAULT_TIMEOUT: f64 = 2.0;
+pub const DEFAULT_SILENCE_BEATS: f64 = 4.0;
+pub const DEFAULT_BEATS_PER_MINUTE: f64 = 120.0;
+/// Longest silence tolerated while a note is still held, so that a lost
+/// note-off cannot keep a recording open forever.
+pub const MAX_HELD_NOTE_SILENCE: f64 = 10.0;
+pub const SUSTAIN_PEDAL: u8 = 64;
 
 pub trait SynthMsgReceiver: Send {
     fn receive(&mut self, msg: SynthMsg);
@@ -69,10 +89,199 @@ pub fn setup_threads<R: SynthMsgReceiver + 'static>(
     Ok(recorder)
 }
 
+/// How incoming MIDI messages are split into separate `Recording`s.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Segmentation {
+    /// Start a new recording after `Recorder::timeout` seconds of silence.
+    Timeout,
+    /// Start a new recording after `Recorder::silence_beats` beats of silence
+    /// at `Recorder::beats_per_minute`.
+    BeatsOfSilence,
+    /// Start a new recording with the first note struck after the sustain pedal
+    /// is released, so that notes still ringing end in the recording they began.
+    SustainRelease,
+    /// Record only while armed, via the Record/Stop buttons or a `RecordTrigger`.
+    Manual,
+}
+
+impl Segmentation {
+    pub fn all() -> [Self; 4] {
+        [
+            Self::Timeout,
+            Self::BeatsOfSilence,
+            Self::SustainRelease,
+            Self::Manual,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Timeout => "Split on silence",
+            Self::BeatsOfSilence => "Split on beats of silence",
+            Self::SustainRelease => "Split on sustain release",
+            Self::Manual => "Record/Stop",
+        }
+    }
+}
+
+/// A MIDI event that toggles recording when using `Segmentation::Manual`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum RecordTrigger {
+    /// Pressing the pedal with this controller number.
+    Pedal(u8),
+    /// Striking this note.
+    Note(u8),
+}
+
+impl RecordTrigger {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pedal(_) => "Pedal",
+            Self::Note(_) => "Note",
+        }
+    }
+
+    /// Whether `msg` presses (`Some(true)`) or releases (`Some(false)`) the
+    /// trigger, or `None` if it is not one of the trigger's own messages.
+    pub fn pressed_by(&self, msg: &MidiMsg) -> Option<bool> {
+        match (self, channel_bytes(msg)?) {
+            (Self::Pedal(control), (CONTROL_CHANGE, _, c, value)) if c == *control => {
+                Some(value >= PEDAL_DOWN)
+            }
+            (Self::Note(pitch), (NOTE_ON, _, note, velocity)) if note == *pitch => {
+                Some(velocity > 0)
+            }
+            (Self::Note(pitch), (NOTE_OFF, _, note, _)) if note == *pitch => Some(false),
+            _ => None,
+        }
+    }
+}
+
+pub const PEDAL_DOWN: u8 = 64;
+pub const CONTROL_CHANGE: u8 = 0xB0;
+
+/// Returns the controller number and value if `msg` is a control change.
+pub fn control_value_from(msg: &MidiMsg) -> Option<(u8, u8)> {
+    let bytes = msg.to_midi();
+    if bytes.len() >= 3 && bytes[0] & 0xF0 == CONTROL_CHANGE {
+        Some((bytes[1], bytes[2]))
+    } else {
+        None
+    }
+}
+
+/// The operation that produced a recording.
+#[derive(Clone, Default, Eq, PartialEq, Debug)]
+pub enum Origin {
+    #[default]
+    Live,
+    /// Played live over a backing of its parent, then merged with it.
+    Overdub,
+    Imported,
+    Variation,
+    Transform(String),
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Live => write!(f, "live"),
+            Self::Overdub => write!(f, "overdub"),
+            Self::Imported => write!(f, "imported"),
+            Self::Variation => write!(f, "variation"),
+            Self::Transform(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// Bookkeeping kept alongside each `Recording`.
+#[derive(Clone, Debug)]
+pub struct RecordingInfo {
+    pub name: String,
+    pub tags: Vec<String>,
+    pub notes: String,
+    pub created: SystemTime,
+    /// The MIDI input port a live recording came from.
+    pub port: String,
+    pub origin: Origin,
+    /// The recording this one was derived from.
+    pub parent: Option<usize>,
+    /// The recording that was played as backing before this one was recorded in practice mode.
+    pub backing: Option<usize>,
+    /// The settings that reproduce this recording, if it is a generated variation.
+    pub generation: Option<VariationParams>,
+    /// The key the user pinned, overriding the detected key.
+    pub key: Option<Key>,
+}
+
+impl Default for RecordingInfo {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            tags: vec![],
+            notes: String::new(),
+            created: SystemTime::now(),
+            port: String::new(),
+            origin: Origin::default(),
+            parent: None,
+            backing: None,
+            generation: None,
+            key: None,
+        }
+    }
+}
+
+impl RecordingInfo {
+    /// The recording's name, or a numbered placeholder if it has none.
+    pub fn label(&self, index: usize) -> String {
+        if self.name.is_empty() {
+            format!("Recording {}", index + 1)
+        } else {
+            self.name.clone()
+        }
+    }
+
+    /// Whether `query` appears, ignoring case, in the name, tags, notes or port.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        [&self.name, &self.notes, &self.port]
+            .into_iter()
+            .chain(self.tags.iter())
+            .any(|field| field.to_lowercase().contains(&query))
+    }
+}
+
 pub struct Recorder {
     pub timeout: f64,
-    recordings: Vec<Recording>,
+    pub silence_beats: f64,
+    pub beats_per_minute: f64,
+    pub segmentation: Segmentation,
+    pub trigger: Option<RecordTrigger>,
+    /// Index of the recording to play back and merge into recordings started
+    /// with `arm` or `start_overdub`.
+    pub overdub: Option<usize>,
+    /// Pitch below which notes are recorded as a separate, lower voice.
+    pub split_point: Option<u8>,
+    overdub_base: Option<Recording>,
+    /// When the backing of an overdub finishes playing; the take stays open until then.
+    backing_end: Instant,
+    armed: bool,
+    /// Whether the trigger is held down, so that only pressing it toggles recording.
+    trigger_down: bool,
+    /// Set when the recordings change underneath a live take, so the next message starts a new one.
+    interrupted: bool,
+    sustain_released: bool,
+    held_notes: BTreeSet<u8>,
+    recordings: Vec<(Recording, RecordingInfo)>,
+    history: History<Revert>,
+    /// History version once the current take was recorded, to tell whether
+    /// its command is still the latest.
+    take_version: u64,
     solo_duration: Option<f64>,
+    solo_backing: usize,
+    solo_start: Instant,
+    /// Set while recording the answer to a practice backing.
+    answering: bool,
     outgoing: Arc<SegQueue<SynthMsg>>,
     last_msg: Instant,
     current_start: Instant,
@@ -88,8 +297,26 @@ impl SynthMsgReceiver for Recorder {
     ) -> Self {
         Self {
             timeout,
+            silence_beats: DEFAULT_SILENCE_BEATS,
+            beats_per_minute: DEFAULT_BEATS_PER_MINUTE,
+            segmentation: Segmentation::Timeout,
+            trigger: None,
+            overdub: None,
+            split_point: None,
+            overdub_base: None,
+            backing_end: Instant::now(),
+            armed: false,
+            trigger_down: false,
+            interrupted: false,
+            sustain_released: false,
+            held_notes: BTreeSet::new(),
             recordings: vec![],
+            history: History::default(),
+            take_version: 0,
             solo_duration: None,
+            solo_backing: 0,
+            solo_start: Instant::now(),
+            answering: false,
             outgoing,
             last_msg: Instant::now(),
             current_start: Instant::now(),
@@ -99,15 +326,65 @@ impl SynthMsgReceiver for Recorder {
 
     fn receive(&mut self, msg: SynthMsg) {
         let now = Instant::now();
-        if !self.actively_recording() {
-            self.recordings.push(Recording::default());
-            self.current_start = now;
+        if self.actively_soloing() {
+            return;
+        }
+        match self.segmentation {
+            Segmentation::Timeout | Segmentation::BeatsOfSilence => {
+                if !self.actively_recording() {
+                    self.start_recording(now, None);
+                }
+            }
+            Segmentation::SustainRelease => {
+                let struck = channel_bytes(&msg.msg)
+                    .is_some_and(|(status, _, _, velocity)| status == NOTE_ON && velocity > 0);
+                if !self.actively_recording()
+                    && (struck || self.interrupted || self.recordings.is_empty())
+                {
+                    self.start_recording(now, None);
+                }
+            }
+            Segmentation::Manual => {
+                if let Some(pressed) = self.trigger.and_then(|t| t.pressed_by(&msg.msg)) {
+                    if pressed && !self.trigger_down {
+                        if self.armed {
+                            self.stop();
+                        } else {
+                            self.arm();
+                        }
+                    }
+                    self.trigger_down = pressed;
+                    return;
+                }
+                if !self.armed {
+                    return;
+                }
+            }
+        }
+        let time = now.duration_since(self.current_start).as_secs_f64();
+        let Some((recording, _)) = self.recordings.last_mut() else {
+            return;
+        };
+        match self.split_point {
+            Some(split) => {
+                for msg in split_keyboard(&msg.msg, split) {
+                    recording.add_message(time, &msg);
+                }
+            }
+            None => recording.add_message(time, &msg.msg),
         }
-        self.recordings.last_mut().unwrap().add_message(
-            now.duration_since(self.current_start).as_secs_f64(),
-            &msg.msg,
-        );
         self.last_msg = now;
+        if let Some((note, velocity)) = note_velocity_from(&msg.msg) {
+            if velocity > 0 {
+                self.held_notes.insert(note);
+            } else {
+                self.held_notes.remove(&note);
+            }
+        } else if let Some((control, value)) = control_value_from(&msg.msg) {
+            if control == SUSTAIN_PEDAL && value < PEDAL_DOWN {
+                self.sustain_released = true;
+            }
+        }
     }
 
     fn live_speaker(&self) -> Speaker {
@@ -126,13 +403,181 @@ impl Recorder {
     }
 
     pub fn delete_last_recording(&mut self) {
-        if self.recordings.len() > 0 {
-            self.recordings.pop();
+        if let Some(last) = self.recordings.pop() {
+            self.change("Delete last recording", Revert::Append(vec![last]));
+        }
+    }
+
+    /// Saves the info of the recording at `index` so that the edits about to
+    /// be made to it through `info_mut` can be undone.
+    pub fn checkpoint_info<S: Into<String>>(&mut self, index: usize, description: S) {
+        let info = self.recordings[index].1.clone();
+        self.history
+            .record(description.into(), Revert::Info(index, info));
+    }
+
+    /// Saves the recording at `index` so that the edits about to be made to it
+    /// through `revise_recording` can be undone.
+    pub fn checkpoint_recording<S: Into<String>>(&mut self, index: usize, description: S) {
+        let recording = self.recordings[index].0.clone();
+        self.history
+            .record(description.into(), Revert::Recording(index, recording));
+    }
+
+    /// Records a change that was just made, given the change that reverses it,
+    /// ending any live take.
+    fn change<S: Into<String>>(&mut self, description: S, inverse: Revert) {
+        self.history.record(description.into(), inverse);
+        self.interrupt();
+    }
+
+    fn interrupt(&mut self) {
+        self.armed = false;
+        self.answering = false;
+        self.interrupted = true;
+        self.overdub_base = None;
+    }
+
+    pub fn undo(&mut self) -> Option<String> {
+        self.interrupt();
+        self.history
+            .undo(|inverse| inverse.apply(&mut self.recordings))
+    }
+
+    pub fn redo(&mut self) -> Option<String> {
+        self.interrupt();
+        self.history
+            .redo(|change| change.apply(&mut self.recordings))
+    }
+
+    /// Descriptions of undoable commands, oldest first.
+    pub fn undo_descriptions(&self) -> impl Iterator<Item = &str> {
+        self.history.undo_descriptions()
+    }
+
+    /// Descriptions of redoable commands, next to redo first.
+    pub fn redo_descriptions(&self) -> impl Iterator<Item = &str> {
+        self.history.redo_descriptions()
+    }
+
+    /// Replaces the recording at `index` with an edited or transformed version.
+    pub fn set_recording(&mut self, index: usize, recording: Recording, description: &str) {
+        let replaced = std::mem::replace(&mut self.recordings[index].0, recording);
+        self.change(description, Revert::Recording(index, replaced));
+    }
+
+    /// Replaces the recording at `index` without a checkpoint, for edits made
+    /// in many small steps after a single `checkpoint_recording`.
+    pub fn revise_recording(&mut self, index: usize, recording: Recording) {
+        self.interrupt();
+        self.recordings[index].0 = recording;
+    }
+
+    /// Removes the recording at `index`. Recordings derived from it are
+    /// reattached to its parent.
+    pub fn delete_recording(&mut self, index: usize) {
+        let description = format!("Delete {}", self.recordings[index].1.label(index));
+        let links = self.links();
+        let removed = self.recordings.remove(index);
+        for (_, info) in self.recordings.iter_mut() {
+            if info.parent == Some(index) {
+                info.parent = removed.1.parent;
+            }
+        }
+        self.relink(|i| index_after_delete(index, i));
+        let inverse = vec![
+            Revert::Insert(index, Box::new(removed)),
+            Revert::Links(links),
+        ];
+        self.change(description, Revert::Sequence(inverse));
+    }
+
+    /// Moves the recording at `from` so that it ends up at position `to`.
+    pub fn move_recording(&mut self, from: usize, to: usize) {
+        let description = format!(
+            "Move {} to position {}",
+            self.recordings[from].1.label(from),
+            to + 1
+        );
+        let links = self.links();
+        let entry = self.recordings.remove(from);
+        self.recordings.insert(to, entry);
+        self.relink(|i| Some(index_after_move(from, to, i)));
+        let inverse = vec![Revert::Move(to, from), Revert::Links(links)];
+        self.change(description, Revert::Sequence(inverse));
+    }
+
+    /// Appends a copy of the recording at `index`, derived from the original.
+    pub fn duplicate_recording(&mut self, index: usize) {
+        let description = format!("Duplicate {}", self.recordings[index].1.label(index));
+        self.change(description, Revert::Truncate(self.recordings.len()));
+        let (recording, mut info) = self.recordings[index].clone();
+        if !info.name.is_empty() {
+            info.name.push_str(" (copy)");
+        }
+        info.origin = Origin::Transform("duplicate".to_owned());
+        info.parent = Some(index);
+        info.created = SystemTime::now();
+        self.recordings.push((recording, info));
+    }
+
+    pub fn clear(&mut self) {
+        let cleared = std::mem::take(&mut self.recordings);
+        self.change("Clear all recordings", Revert::Append(cleared));
+    }
+
+    /// The parent and backing links of every recording.
+    fn links(&self) -> Vec<(Option<usize>, Option<usize>)> {
+        self.recordings
+            .iter()
+            .map(|(_, info)| (info.parent, info.backing))
+            .collect()
+    }
+
+    fn relink<F: Fn(usize) -> Option<usize>>(&mut self, map: F) {
+        for (_, info) in self.recordings.iter_mut() {
+            info.parent = info.parent.and_then(&map);
+            info.backing = info.backing.and_then(&map);
         }
     }
 
     pub fn add_recording(&mut self, recording: Recording) {
-        self.recordings.push(recording);
+        self.add_recording_with(recording, RecordingInfo::default());
+    }
+
+    pub fn add_recording_with(&mut self, recording: Recording, info: RecordingInfo) {
+        let description = format!("Add {} recording", info.origin);
+        self.change(description, Revert::Truncate(self.recordings.len()));
+        self.recordings.push((recording, info));
+    }
+
+    pub fn info(&self, index: usize) -> &RecordingInfo {
+        &self.recordings[index].1
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Recording> {
+        self.recordings.get(index).map(|(recording, _)| recording)
+    }
+
+    pub fn info_mut(&mut self, index: usize) -> &mut RecordingInfo {
+        &mut self.recordings[index].1
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(Recording, RecordingInfo)> {
+        self.recordings.iter()
+    }
+
+    /// Appends recordings loaded together, such as from a session file, whose
+    /// links refer to positions within `loaded`.
+    pub fn extend(&mut self, loaded: Vec<(Recording, RecordingInfo)>) {
+        let offset = self.recordings.len();
+        let description = format!("Load {} recordings", loaded.len());
+        self.change(description, Revert::Truncate(offset));
+        for (recording, mut info) in loaded {
+            info.parent = info.parent.map(|i| i + offset);
+            info.backing = info.backing.map(|i| i + offset);
+            self.recordings.push((recording, info));
+        }
     }
 
     pub fn is_empty(&self) -> bool {
@@ -143,30 +588,190 @@ impl Recorder {
         self.input_port_name.as_str()
     }
 
-    pub fn actively_recording(&self) -> bool {
-        if let Some(recent) = self.recordings.last() {
-            if let Some((_, last_msg)) = recent.last() {
-                if let Some((_, velocity)) = note_velocity_from(&last_msg) {
-                    return velocity > 0
-                        || Instant::now().duration_since(self.last_msg).as_secs_f64()
-                            < self.timeout;
+    /// Starts a new recording that collects every message until `stop()` is called.
+    pub fn arm(&mut self) {
+        self.start_recording(Instant::now(), self.overdub);
+        self.armed = true;
+    }
+
+    /// Starts recording over `overdub` right away, so that its backing plays
+    /// as a lead-in. The take then ends as the segmentation decides, but not
+    /// before the backing has finished.
+    pub fn start_overdub(&mut self) {
+        let now = Instant::now();
+        self.start_recording(now, self.overdub);
+        self.last_msg = now;
+    }
+
+    pub fn stop(&mut self) {
+        self.armed = false;
+        self.answering = false;
+        self.discard_empty_take();
+        self.finish_overdub();
+    }
+
+    /// Removes the take just recorded if no notes were played, along with its
+    /// command if nothing was done since.
+    fn discard_empty_take(&mut self) {
+        if self
+            .recordings
+            .last()
+            .is_some_and(|(r, _)| note_events(r).is_empty())
+        {
+            let take = self.recordings.pop().unwrap();
+            if self.history.version() == self.take_version {
+                self.history.cancel();
+            } else {
+                self.history
+                    .record("Discard empty take".to_owned(), Revert::Append(vec![take]));
+            }
+            self.overdub_base = None;
+        }
+    }
+
+    /// Completes any overdub whose recording has ended, starts recording the
+    /// answer once a practice backing has finished and ends the answer as the
+    /// segmentation decides; call once per frame.
+    pub fn update(&mut self) {
+        if self.overdub_base.is_some() && !self.actively_recording() {
+            self.finish_overdub();
+        }
+        if let Some(duration) = self.solo_duration {
+            let now = Instant::now();
+            if now.duration_since(self.solo_start).as_secs_f64() >= duration {
+                self.solo_duration = None;
+                self.start_recording(now, None);
+                self.recordings.last_mut().unwrap().1.backing = Some(self.solo_backing);
+                self.armed = true;
+                self.answering = true;
+                self.last_msg = now;
+            }
+        }
+        if self.answering && !self.actively_recording() {
+            self.armed = false;
+            self.answering = false;
+            self.discard_empty_take();
+        }
+    }
+
+    pub fn overdubbing(&self) -> bool {
+        self.overdub_base.is_some()
+    }
+
+    /// Merges the take into the recording it was played over, moving the take
+    /// to a channel of its own so that the two parts stay apart as voices. A
+    /// take with no notes is dropped instead.
+    fn finish_overdub(&mut self) {
+        if self.overdub_base.is_some() {
+            self.discard_empty_take();
+        }
+        if let Some(base) = self.overdub_base.take() {
+            if let Some((take, _)) = self.recordings.last_mut() {
+                if let Some(channel) = free_channel(&base) {
+                    *take = on_channel(take, channel);
                 }
+                *take = merge_recordings(&base, take);
+            }
+        }
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.armed
+    }
+
+    /// Starts a new take, playing and later merging the recording at `overdub` if given.
+    fn start_recording(&mut self, now: Instant, overdub: Option<usize>) {
+        self.finish_overdub();
+        let base = overdub.filter(|i| *i < self.recordings.len());
+        let backing = base.map(|i| self[i].clone());
+        let info = RecordingInfo {
+            origin: if base.is_some() {
+                Origin::Overdub
+            } else {
+                Origin::Live
+            },
+            parent: base,
+            port: self.input_port_name.clone(),
+            ..Default::default()
+        };
+        self.history
+            .record("Record".to_owned(), Revert::Truncate(self.recordings.len()));
+        self.take_version = self.history.version();
+        self.interrupted = false;
+        self.recordings.push((Recording::default(), info));
+        self.current_start = now;
+        self.held_notes.clear();
+        self.sustain_released = false;
+        if let Some(backing) = backing {
+            let length = backing.midi_queue().back().map_or(0.0, |(time, _)| *time);
+            self.backing_end = now + Duration::from_secs_f64(length);
+            self.play(backing.clone(), Arc::new(AtomicCell::new(None)));
+            self.overdub_base = Some(backing);
+        }
+    }
+
+    /// Seconds of silence after which `Segmentation::Timeout` and
+    /// `Segmentation::BeatsOfSilence` start a new recording.
+    pub fn silence_limit(&self) -> f64 {
+        match self.segmentation {
+            Segmentation::BeatsOfSilence => self.silence_beats * 60.0 / self.beats_per_minute,
+            _ => self.timeout,
+        }
+    }
+
+    pub fn actively_recording(&self) -> bool {
+        if self.recordings.is_empty() || self.interrupted {
+            return false;
+        }
+        let silence = Instant::now().duration_since(self.last_msg).as_secs_f64();
+        match self.segmentation {
+            Segmentation::Manual => self.armed,
+            Segmentation::SustainRelease => !self.sustain_released,
+            Segmentation::Timeout | Segmentation::BeatsOfSilence => {
+                silence < self.silence_limit()
+                    || (!self.held_notes.is_empty() && silence < MAX_HELD_NOTE_SILENCE)
+                    || (self.overdub_base.is_some() && Instant::now() < self.backing_end)
             }
         }
-        false
     }
 
     pub fn actively_soloing(&self) -> bool {
         self.solo_duration.is_some()
     }
 
+    /// Plays `backing` for `duration` seconds, ignoring live input, then
+    /// starts recording the answering phrase.
+    pub fn start_solo(&mut self, backing: usize, duration: f64) {
+        self.solo_duration = Some(duration);
+        self.solo_backing = backing;
+        self.solo_start = Instant::now();
+        let phrase = truncate_recording(&self[backing], duration);
+        self.play(phrase, Arc::new(AtomicCell::new(None)));
+    }
+
+    /// Whether the answer to a practice backing is being recorded.
+    pub fn answering(&self) -> bool {
+        self.answering
+    }
+
+    /// Abandons any practice run, ending its answer if one is being recorded.
+    pub fn cancel_solo(&mut self) {
+        self.solo_duration = None;
+        if self.answering {
+            self.stop();
+        }
+    }
+
     pub fn start_playback_thread(
         &mut self,
         selected: usize,
         playback_progress: Arc<AtomicCell<Option<f64>>>,
     ) {
-        let backing = self.recordings[selected].clone();
         self.current_start = Instant::now();
+        self.play(self[selected].clone(), playback_progress);
+    }
+
+    fn play(&self, backing: Recording, playback_progress: Arc<AtomicCell<Option<f64>>>) {
         let outgoing = self.outgoing.clone();
         std::thread::spawn(move || {
             backing.playback_loop(
@@ -182,10 +787,183 @@ impl Recorder {
     }
 }
 
+/// A change to the recordings that reverses a command. Applying it returns
+/// the change that reverses it in turn.
+enum Revert {
+    /// Remove the recordings from this position on.
+    Truncate(usize),
+    /// Append these recordings.
+    Append(Vec<(Recording, RecordingInfo)>),
+    /// Swap the recording at this position with this one.
+    Recording(usize, Recording),
+    /// Swap the info at this position with this one.
+    Info(usize, RecordingInfo),
+    Insert(usize, Box<(Recording, RecordingInfo)>),
+    Remove(usize),
+    /// Move the recording at the first position to the second.
+    Move(usize, usize),
+    /// Set the parent and backing links of every recording.
+    Links(Vec<(Option<usize>, Option<usize>)>),
+    /// Apply these in order.
+    Sequence(Vec<Revert>),
+}
+
+impl Revert {
+    fn apply(self, recordings: &mut Vec<(Recording, RecordingInfo)>) -> Self {
+        match self {
+            Self::Truncate(len) => Self::Append(recordings.split_off(len)),
+            Self::Append(entries) => {
+                let len = recordings.len();
+                recordings.extend(entries);
+                Self::Truncate(len)
+            }
+            Self::Recording(index, recording) => Self::Recording(
+                index,
+                std::mem::replace(&mut recordings[index].0, recording),
+            ),
+            Self::Info(index, info) => {
+                Self::Info(index, std::mem::replace(&mut recordings[index].1, info))
+            }
+            Self::Insert(index, entry) => {
+                recordings.insert(index, *entry);
+                Self::Remove(index)
+            }
+            Self::Remove(index) => Self::Insert(index, Box::new(recordings.remove(index))),
+            Self::Move(from, to) => {
+                let entry = recordings.remove(from);
+                recordings.insert(to, entry);
+                Self::Move(to, from)
+            }
+            Self::Links(links) => Self::Links(
+                recordings
+                    .iter_mut()
+                    .zip(links)
+                    .map(|((_, info), (parent, backing))| {
+                        let replaced = (info.parent, info.backing);
+                        info.parent = parent;
+                        info.backing = backing;
+                        replaced
+                    })
+                    .collect(),
+            ),
+            Self::Sequence(changes) => {
+                let mut inverses = changes
+                    .into_iter()
+                    .map(|change| change.apply(recordings))
+                    .collect::<Vec<_>>();
+                inverses.reverse();
+                Self::Sequence(inverses)
+            }
+        }
+    }
+}
+
+/// Where a recording at `index` ends up after the one at `deleted` is removed.
+pub fn index_after_delete(deleted: usize, index: usize) -> Option<usize> {
+    if index == deleted {
+        None
+    } else if index > deleted {
+        Some(index - 1)
+    } else {
+        Some(index)
+    }
+}
+
+/// Where a recording at `index` ends up after the one at `from` is moved to `to`.
+pub fn index_after_move(from: usize, to: usize, index: usize) -> usize {
+    if index == from {
+        to
+    } else if from < index && index <= to {
+        index - 1
+    } else if to <= index && index < from {
+        index + 1
+    } else {
+        index
+    }
+}
+
+/// The lowest channel that no note of `recording` is played on, if any.
+pub fn free_channel(recording: &Recording) -> Option<u8> {
+    let used = note_events(recording)
+        .iter()
+        .map(|e| e.channel)
+        .collect::<BTreeSet<_>>();
+    (0..NUM_CHANNELS as u8).find(|channel| !used.contains(channel))
+}
+
+/// `recording` with every channel message moved to `channel`.
+fn on_channel(recording: &Recording, channel: u8) -> Recording {
+    let mut moved = Recording::default();
+    for (time, msg) in recording.midi_queue() {
+        match channel_bytes(&msg) {
+            Some((status, _, data1, data2)) => {
+                moved.add_message(time, &channel_msg(status, channel, data1, data2))
+            }
+            None => moved.add_message(time, &msg),
+        }
+    }
+    moved
+}
+
+/// The messages of `recording` before `end`, with any notes and sustain pedal
+/// still held at `end` released there.
+pub fn truncate_recording(recording: &Recording, end: f64) -> Recording {
+    let mut truncated = Recording::default();
+    let mut held = BTreeSet::new();
+    let mut pedals = BTreeSet::new();
+    for (time, msg) in recording.midi_queue() {
+        if time >= end {
+            break;
+        }
+        truncated.add_message(time, &msg);
+        match channel_bytes(&msg) {
+            Some((NOTE_ON, channel, pitch, velocity)) if velocity > 0 => {
+                held.insert((channel, pitch));
+            }
+            Some((NOTE_ON | NOTE_OFF, channel, pitch, _)) => {
+                held.remove(&(channel, pitch));
+            }
+            Some((CONTROL_CHANGE, channel, SUSTAIN_PEDAL, value)) => {
+                if value >= PEDAL_DOWN {
+                    pedals.insert(channel);
+                } else {
+                    pedals.remove(&channel);
+                }
+            }
+            _ => {}
+        }
+    }
+    for (channel, pitch) in held {
+        truncated.add_message(end, &channel_msg(NOTE_OFF, channel, pitch, 0));
+    }
+    for channel in pedals {
+        truncated.add_message(end, &channel_msg(CONTROL_CHANGE, channel, SUSTAIN_PEDAL, 0));
+    }
+    truncated
+}
+
+/// Interleaves the messages of `a` and `b` in timestamp order.
+pub fn merge_recordings(a: &Recording, b: &Recording) -> Recording {
+    let mut a = a.midi_queue();
+    let mut b = b.midi_queue();
+    let mut merged = Recording::default();
+    loop {
+        let take_a = match (a.front(), b.front()) {
+            (Some((ta, _)), Some((tb, _))) => ta <= tb,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => return merged,
+        };
+        let next = if take_a { a.pop_front() } else { b.pop_front() };
+        let (time, msg) = next.unwrap();
+        merged.add_message(time, &msg);
+    }
+}
+
 impl Index<usize> for Recorder {
     type Output = Recording;
 
     fn index(&self, index: usize) -> &Self::Output {
-        &self.recordings[index]
+        &self.recordings[index].0
     }
 }
@@ -0,0 +1,117 @@
+use std::cmp::Reverse;
+
+use crate::note_events::NoteEvent;
+
+/// Notes starting within this many seconds of each other are struck together.
+pub(crate) const CHORD_TOLERANCE: f64 = 0.05;
+
+/// Ways of picking a single melody line out of a recording with chords.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Reduction {
+    /// The highest note sounding, including notes still held.
+    Highest,
+    /// The lowest note sounding, including notes still held.
+    Lowest,
+    /// The note struck most recently.
+    MostRecent,
+    /// The newly struck note closest to the previous note of the line.
+    VoiceLeading,
+}
+
+impl Reduction {
+    pub fn all() -> [Self; 4] {
+        [
+            Self::Highest,
+            Self::Lowest,
+            Self::MostRecent,
+            Self::VoiceLeading,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Highest => "Highest note",
+            Self::Lowest => "Lowest note",
+            Self::MostRecent => "Most recent note",
+            Self::VoiceLeading => "Voice leading",
+        }
+    }
+}
+
+/// The outcome of `reduce`. Indices refer to the events it was given.
+#[derive(Clone, Debug, Default)]
+pub struct Reduced {
+    /// The melody line, each note cut short where the next begins.
+    pub line: Vec<NoteEvent>,
+    /// The event behind each note of `line`.
+    pub kept: Vec<usize>,
+    pub discarded: Vec<usize>,
+}
+
+/// Reduces `events` to a line in which only one note sounds at a time.
+pub fn reduce(events: &[NoteEvent], reduction: Reduction) -> Reduced {
+    let mut order = (0..events.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| {
+        events[*a]
+            .onset
+            .total_cmp(&events[*b].onset)
+            .then(events[*a].pitch.cmp(&events[*b].pitch))
+    });
+    let mut kept: Vec<usize> = vec![];
+    let mut discarded = vec![];
+    let mut start = 0;
+    while start < order.len() {
+        let onset = events[order[start]].onset;
+        let struck = order[start..]
+            .iter()
+            .take_while(|i| events[**i].onset - onset <= CHORD_TOLERANCE)
+            .count();
+        let group = &order[start..start + struck];
+        let held = kept.last().copied().filter(|i| events[*i].end() > onset);
+        let pitch = |i: &usize| events[*i].pitch;
+        let choice = match reduction {
+            Reduction::Highest => group.iter().copied().chain(held).max_by_key(pitch),
+            Reduction::Lowest => group.iter().copied().chain(held).min_by_key(pitch),
+            Reduction::MostRecent => group.iter().copied().max_by(|a, b| {
+                events[*a]
+                    .onset
+                    .total_cmp(&events[*b].onset)
+                    .then(events[*a].pitch.cmp(&events[*b].pitch))
+            }),
+            Reduction::VoiceLeading => match kept.last() {
+                Some(previous) => {
+                    let previous = events[*previous].pitch;
+                    group
+                        .iter()
+                        .copied()
+                        .min_by_key(|i| (pitch(i).abs_diff(previous), Reverse(pitch(i))))
+                }
+                None => group.iter().copied().max_by_key(pitch),
+            },
+        };
+        for i in group.iter() {
+            if Some(*i) == choice {
+                kept.push(*i);
+            } else {
+                discarded.push(*i);
+            }
+        }
+        start += struck;
+    }
+    let line = kept
+        .iter()
+        .enumerate()
+        .map(|(k, i)| {
+            let mut event = events[*i];
+            if let Some(next) = kept.get(k + 1) {
+                event.duration = event.duration.min(events[*next].onset - event.onset);
+            }
+            event
+        })
+        .collect();
+    Reduced {
+        line,
+        kept,
+        discarded,
+    }
+}
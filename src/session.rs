@@ -0,0 +1,219 @@
+use std::{
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail};
+use midi_msg::MidiMsg;
+use midi_note_recorder::Recording;
+
+use crate::{
+    chords::NUM_PITCH_CLASSES,
+    keys::{Key, Mode},
+    recorder::{Origin, Recorder, RecordingInfo},
+    variation::VariationParams,
+};
+
+pub const SESSION_EXTENSION: &str = "mmg";
+const RECORDING_HEADER: &str = "[recording]";
+
+/// Writes every recording in `recorder`, with its metadata, to a plain-text session file.
+pub fn save_session(path: &Path, recorder: &Recorder) -> anyhow::Result<()> {
+    let mut text = String::new();
+    for (recording, info) in recorder.iter() {
+        text.push_str(RECORDING_HEADER);
+        text.push('\n');
+        write_info(&mut text, info);
+        for (time, msg) in recording.midi_queue() {
+            let bytes = msg
+                .to_midi()
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<Vec<_>>()
+                .join("");
+            text.push_str(&format!("msg={time} {bytes}\n"));
+        }
+    }
+    std::fs::write(path, text)?;
+    Ok(())
+}
+
+/// Reads the recordings saved by `save_session`. Parent and backing links
+/// refer to positions within the returned vector.
+pub fn load_session(path: &Path) -> anyhow::Result<Vec<(Recording, RecordingInfo)>> {
+    let text = std::fs::read_to_string(path)?;
+    let mut result: Vec<(Recording, RecordingInfo)> = vec![];
+    for (line_num, line) in text.lines().enumerate() {
+        if line == RECORDING_HEADER {
+            result.push((Recording::default(), RecordingInfo::default()));
+        } else if !line.is_empty() {
+            let Some((recording, info)) = result.last_mut() else {
+                bail!("line {}: expected {RECORDING_HEADER}", line_num + 1);
+            };
+            read_line(line, recording, info).map_err(|e| anyhow!("line {}: {e}", line_num + 1))?;
+        }
+    }
+    Ok(result)
+}
+
+fn write_info(text: &mut String, info: &RecordingInfo) {
+    let created = info
+        .created
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    text.push_str(&format!("name={}\n", escape(&info.name)));
+    text.push_str(&format!("tags={}\n", escape(&info.tags.join(","))));
+    text.push_str(&format!("notes={}\n", escape(&info.notes)));
+    text.push_str(&format!("created={created}\n"));
+    text.push_str(&format!("port={}\n", escape(&info.port)));
+    text.push_str(&format!("origin={}\n", escape(&origin_code(&info.origin))));
+    if let Some(parent) = info.parent {
+        text.push_str(&format!("parent={parent}\n"));
+    }
+    if let Some(backing) = info.backing {
+        text.push_str(&format!("backing={backing}\n"));
+    }
+    if let Some(key) = info.key {
+        text.push_str(&format!("key={} {}\n", key.tonic, key.mode.name()));
+    }
+    if let Some(p) = info.generation {
+        text.push_str(&format!(
+            "generation={} {} {} {} {} {}\n",
+            p.seed, p.count, p.keep_rhythm, p.keep_contour, p.stay_in_scale, p.max_leap
+        ));
+    }
+}
+
+fn read_line(
+    line: &str,
+    recording: &mut Recording,
+    info: &mut RecordingInfo,
+) -> anyhow::Result<()> {
+    let (key, value) = line
+        .split_once('=')
+        .ok_or_else(|| anyhow!("expected key=value"))?;
+    match key {
+        "name" => info.name = unescape(value),
+        "tags" => {
+            info.tags = unescape(value)
+                .split(',')
+                .filter(|t| !t.is_empty())
+                .map(|t| t.to_owned())
+                .collect()
+        }
+        "notes" => info.notes = unescape(value),
+        "created" => info.created = UNIX_EPOCH + Duration::from_secs(value.parse()?),
+        "port" => info.port = unescape(value),
+        "origin" => info.origin = parse_origin(&unescape(value)),
+        "parent" => info.parent = Some(value.parse()?),
+        "backing" => info.backing = Some(value.parse()?),
+        "key" => info.key = Some(parse_key(value)?),
+        "generation" => info.generation = Some(parse_generation(value)?),
+        "msg" => {
+            let (time, hex) = value
+                .split_once(' ')
+                .ok_or_else(|| anyhow!("expected time and bytes"))?;
+            if !hex.is_ascii() || hex.len() % 2 != 0 {
+                bail!("expected an even number of hex digits");
+            }
+            let bytes = (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+                .collect::<Result<Vec<_>, _>>()?;
+            let (msg, _) = MidiMsg::from_midi(&bytes).map_err(|e| anyhow!("{e:?}"))?;
+            recording.add_message(time.parse()?, &msg);
+        }
+        _ => bail!("unknown key {key}"),
+    }
+    Ok(())
+}
+
+fn parse_generation(value: &str) -> anyhow::Result<VariationParams> {
+    let fields = value.split(' ').collect::<Vec<_>>();
+    if fields.len() != 6 {
+        bail!("expected 6 generation fields");
+    }
+    Ok(VariationParams {
+        seed: fields[0].parse()?,
+        count: fields[1].parse()?,
+        keep_rhythm: fields[2].parse()?,
+        keep_contour: fields[3].parse()?,
+        stay_in_scale: fields[4].parse()?,
+        max_leap: fields[5].parse()?,
+    })
+}
+
+/// Reads a key written as its tonic's pitch class and its mode.
+fn parse_key(value: &str) -> anyhow::Result<Key> {
+    let (tonic, mode) = value
+        .split_once(' ')
+        .ok_or_else(|| anyhow!("expected a tonic and a mode"))?;
+    let tonic = tonic.parse()?;
+    if tonic >= NUM_PITCH_CLASSES {
+        bail!("expected a pitch class below {NUM_PITCH_CLASSES}");
+    }
+    let mode = Mode::from_name(mode).ok_or_else(|| anyhow!("unknown mode {mode}"))?;
+    Ok(Key { tonic, mode })
+}
+
+fn origin_code(origin: &Origin) -> String {
+    match origin {
+        Origin::Transform(name) => format!("transform:{name}"),
+        other => other.to_string(),
+    }
+}
+
+fn parse_origin(code: &str) -> Origin {
+    match code {
+        "overdub" => Origin::Overdub,
+        "imported" => Origin::Imported,
+        "variation" => Origin::Variation,
+        _ => match code.strip_prefix("transform:") {
+            Some(name) => Origin::Transform(name.to_owned()),
+            None => Origin::Live,
+        },
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => result.push('\n'),
+                Some(other) => result.push(other),
+                None => {}
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Formats `time` as a UTC date and time, e.g. `2024-05-01 13:45:10`.
+pub fn format_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, day_secs) = (secs / 86400, secs % 86400);
+    // Civil-from-days conversion, after Howard Hinnant's date algorithms.
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        day_secs / 3600,
+        (day_secs % 3600) / 60,
+        day_secs % 60
+    )
+}
@@ -0,0 +1,168 @@
+use std::fmt::Display;
+
+use midi_note_recorder::Recording;
+use music_analyzer_generator::scales::RootedScale;
+
+use crate::note_events::{NoteEvent, note_events, recording_from};
+
+const RHYTHM_FACTORS: [f64; 4] = [0.5, 1.0, 1.5, 2.0];
+/// Largest number of semitones by which a varied interval may differ from the
+/// closest one the constraints allow.
+const MAX_INTERVAL_CHANGE: u8 = 2;
+
+/// Settings for `generate_variations`. Variation `k` of a batch uses `seed + k`,
+/// so any variation can be reproduced from the seed stored with it.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct VariationParams {
+    pub seed: u64,
+    pub count: usize,
+    pub keep_rhythm: bool,
+    pub keep_contour: bool,
+    pub stay_in_scale: bool,
+    /// Largest interval, in semitones, between consecutive varied notes.
+    pub max_leap: u8,
+}
+
+impl Default for VariationParams {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            count: 1,
+            keep_rhythm: true,
+            keep_contour: false,
+            stay_in_scale: true,
+            max_leap: 7,
+        }
+    }
+}
+
+impl Display for VariationParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "seed {}, max leap {}", self.seed, self.max_leap)?;
+        if self.keep_rhythm {
+            write!(f, ", keep rhythm")?;
+        }
+        if self.keep_contour {
+            write!(f, ", keep contour")?;
+        }
+        if self.stay_in_scale {
+            write!(f, ", in scale")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum VariationError {
+    NoNotes,
+    /// No pitch for this note satisfies every constraint.
+    Unsatisfiable {
+        note: usize,
+        pitch: u8,
+    },
+}
+
+impl Display for VariationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoNotes => write!(f, "the recording has no notes to vary"),
+            Self::Unsatisfiable { note, pitch } => write!(
+                f,
+                "no pitch for note {} (originally {pitch}) satisfies the constraints",
+                note + 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VariationError {}
+
+/// Small deterministic generator (SplitMix64), so seeds reproduce across runs.
+struct SeededRng(u64);
+
+impl SeededRng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// Produces `params.count` variations of `recording`, each paired with the
+/// parameters (including its own seed) that reproduce it.
+pub fn generate_variations(
+    recording: &Recording,
+    scale: &RootedScale,
+    params: &VariationParams,
+) -> Result<Vec<(Recording, VariationParams)>, VariationError> {
+    let notes = note_events(recording);
+    if notes.is_empty() {
+        return Err(VariationError::NoNotes);
+    }
+    (0..params.count as u64)
+        .map(|k| {
+            let params = VariationParams {
+                seed: params.seed.wrapping_add(k),
+                count: 1,
+                ..*params
+            };
+            vary(&notes, scale, &params).map(|v| (recording_from(&v), params))
+        })
+        .collect()
+}
+
+/// Varies `notes` by perturbing each interval between consecutive notes, and
+/// their durations unless `keep_rhythm` is set. Each note is placed the
+/// source's interval away from the previous varied note, give or take up to
+/// `MAX_INTERVAL_CHANGE` semitones, among the pitches the constraints allow.
+fn vary(
+    notes: &[NoteEvent],
+    scale: &RootedScale,
+    params: &VariationParams,
+) -> Result<Vec<NoteEvent>, VariationError> {
+    let mut rng = SeededRng(params.seed);
+    let mut result: Vec<NoteEvent> = vec![];
+    let mut shift = 0.0;
+    for (i, note) in notes.iter().enumerate() {
+        let previous = result.last().map(|p| (notes[i - 1].pitch, p.pitch));
+        let anchor = previous.map_or(note.pitch, |(_, new)| new);
+        let target = previous.map_or(note.pitch as i16, |(old, new)| {
+            new as i16 + note.pitch as i16 - old as i16
+        });
+        let allowed = (0..=127_u8)
+            .filter(|p| p.abs_diff(anchor) <= params.max_leap)
+            .filter(|p| !params.stay_in_scale || scale.contains(*p))
+            .filter(|p| {
+                !params.keep_contour
+                    || previous.is_none_or(|(old, new)| note.pitch.cmp(&old) == p.cmp(&new))
+            })
+            .collect::<Vec<_>>();
+        let distance = |p: &u8| (*p as i16).abs_diff(target);
+        let Some(closest) = allowed.iter().map(distance).min() else {
+            return Err(VariationError::Unsatisfiable {
+                note: i,
+                pitch: note.pitch,
+            });
+        };
+        let candidates = allowed
+            .into_iter()
+            .filter(|p| distance(p) <= closest + MAX_INTERVAL_CHANGE as u16)
+            .collect::<Vec<_>>();
+        let mut varied = *note;
+        varied.pitch = candidates[rng.below(candidates.len())];
+        varied.onset += shift;
+        if !params.keep_rhythm {
+            let factor = RHYTHM_FACTORS[rng.below(RHYTHM_FACTORS.len())];
+            shift += varied.duration * (factor - 1.0);
+            varied.duration *= factor;
+        }
+        result.push(varied);
+    }
+    Ok(result)
+}
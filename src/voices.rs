@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+
+use midi_msg::MidiMsg;
+use midi_note_recorder::Recording;
+
+use crate::{
+    note_events::{NOTE_OFF, NOTE_ON, channel_bytes, channel_msg},
+    recorder::NUM_CHANNELS,
+};
+
+pub const DEFAULT_SPLIT_POINT: u8 = 60;
+
+/// How the voices of a recording that uses several channels are shown.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum VoiceDisplay {
+    /// All channels as a single line, as if played on one channel.
+    #[default]
+    Merged,
+    /// Each channel in its own color on a shared staff.
+    Colored,
+    /// Each channel on its own staff.
+    Separate,
+}
+
+impl VoiceDisplay {
+    pub fn all() -> [Self; 3] {
+        [Self::Merged, Self::Colored, Self::Separate]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Merged => "Merged",
+            Self::Colored => "Colored voices",
+            Self::Separate => "Separate staves",
+        }
+    }
+}
+
+/// Channel given to notes played below the split point of a keyboard that
+/// transmits on `channel`: the next one, so that the two halves never share
+/// a channel whichever one the keyboard uses.
+pub fn lower_voice_channel(channel: u8) -> u8 {
+    (channel + 1) % NUM_CHANNELS as u8
+}
+
+/// Routes `msg` as played on a keyboard split at `split`: notes below it move
+/// to the `lower_voice_channel`, and other channel messages, such as the
+/// sustain pedal, are copied there so that they still govern both halves.
+pub fn split_keyboard(msg: &MidiMsg, split: u8) -> Vec<MidiMsg> {
+    match channel_bytes(msg) {
+        Some((status, channel, pitch, velocity)) if status == NOTE_ON || status == NOTE_OFF => {
+            if pitch < split {
+                let lower = lower_voice_channel(channel);
+                vec![channel_msg(status, lower, pitch, velocity)]
+            } else {
+                vec![msg.clone()]
+            }
+        }
+        Some((status, channel, data1, data2)) => vec![
+            msg.clone(),
+            channel_msg(status, lower_voice_channel(channel), data1, data2),
+        ],
+        _ => vec![msg.clone()],
+    }
+}
+
+/// Splits `recording` into one recording per channel that plays notes, in
+/// channel order. Messages that `channel_bytes` cannot place on a channel go
+/// to every voice.
+pub fn voices(recording: &Recording) -> Vec<(u8, Recording)> {
+    let queue = recording.midi_queue();
+    let mut streams = BTreeMap::new();
+    for (_, msg) in queue.iter() {
+        if let Some((status, channel, _, _)) = channel_bytes(msg) {
+            if status == NOTE_ON {
+                streams.entry(channel).or_insert_with(Recording::default);
+            }
+        }
+    }
+    for (time, msg) in queue.iter() {
+        match channel_bytes(msg) {
+            Some((_, channel, _, _)) => {
+                if let Some(stream) = streams.get_mut(&channel) {
+                    stream.add_message(*time, msg);
+                }
+            }
+            None => {
+                for stream in streams.values_mut() {
+                    stream.add_message(*time, msg);
+                }
+            }
+        }
+    }
+    streams.into_iter().collect()
+}